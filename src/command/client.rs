@@ -4,15 +4,20 @@
  * Copyright (c) storycraft. Licensed under the MIT Licence.
  */
 
-use alloc::{boxed::Box, collections::VecDeque};
-use core::mem;
+use alloc::{
+    boxed::Box,
+    collections::{vec_deque::Drain, BTreeSet, VecDeque},
+    vec::Vec,
+};
+use core::{error::Error, fmt::Display, iter::FusedIterator, mem};
 
 use arrayvec::ArrayVec;
+use bincode::Options;
 use serde::{Deserialize, Serialize};
 
 use crate::command::Header;
 
-use super::Command;
+use super::{codec, BoxedCommand, Command, HEADER_SIZE, HEAD_SIZE};
 
 #[derive(Debug)]
 #[non_exhaustive]
@@ -34,17 +39,42 @@ impl LocoSink {
     pub fn send(&mut self, command: Command<impl AsRef<[u8]>>) {
         let data = command.data.as_ref();
 
-        bincode::serialize_into(
-            &mut self.write_buffer,
-            &RawHeader {
-                header: command.header,
-                data_size: data.len() as u32,
-            },
-        )
-        .unwrap();
-
+        self.write_buffer
+            .extend(self.encode_header(&command.header, data.len() as u32));
         self.write_buffer.extend(data);
     }
+
+    /// Serialize command header and data size only
+    ///
+    /// Writing returned bytes followed by `data_len` bytes of raw data is identical to [`LocoSink::send`] on wire
+    pub fn encode_header(&self, command_header: &Header, data_len: u32) -> [u8; HEAD_SIZE] {
+        let mut buf = [0_u8; HEAD_SIZE];
+
+        codec::bincode_options()
+            .serialize_into(&mut buf[..], &(command_header, data_len))
+            .unwrap();
+
+        buf
+    }
+}
+
+#[cfg(feature = "std")]
+impl LocoSink {
+    /// Write [`LocoSink::write_buffer`] to `writer` using single vectored write
+    ///
+    /// Only bytes accepted by `writer` are removed from buffer, so remaining bytes can be flushed later.
+    ///
+    /// Returns number of bytes written
+    pub fn flush_to<W: std::io::Write>(&mut self, writer: &mut W) -> std::io::Result<usize> {
+        let written = {
+            let (front, back) = self.write_buffer.as_slices();
+
+            writer.write_vectored(&[std::io::IoSlice::new(front), std::io::IoSlice::new(back)])?
+        };
+
+        self.write_buffer.drain(..written);
+        Ok(written)
+    }
 }
 
 impl Default for LocoSink {
@@ -58,6 +88,11 @@ impl Default for LocoSink {
 pub struct LocoStream {
     state: StreamState,
 
+    max_data_size: u32,
+
+    /// Error hit by [`LocoStream::drain_up_to`] after reading commands, reported by next read
+    deferred_error: Option<StreamError>,
+
     /// Read buffer for stream
     pub read_buffer: VecDeque<u8>,
 }
@@ -65,55 +100,199 @@ pub struct LocoStream {
 impl LocoStream {
     /// Create new [`LocoStream`]
     pub const fn new() -> Self {
+        Self::with_max_data_size(DEFAULT_MAX_DATA_SIZE)
+    }
+
+    /// Create new [`LocoStream`] with given maximum command data size
+    pub const fn with_max_data_size(max_data_size: u32) -> Self {
         Self {
             state: StreamState::Pending,
+            max_data_size,
+            deferred_error: None,
             read_buffer: VecDeque::new(),
         }
     }
 
+    /// Maximum command data size
+    pub const fn max_data_size(&self) -> u32 {
+        self.max_data_size
+    }
+
+    /// Append bytes to [`LocoStream::read_buffer`]
+    pub fn feed_slice(&mut self, buf: &[u8]) {
+        self.read_buffer.extend(buf);
+    }
+
     pub const fn state(&self) -> &StreamState {
         &self.state
     }
 
-    /// Try reading single [`Command`] from [`LocoClient::read_buffer`]
-    pub fn read(&mut self) -> Option<Command<Box<[u8]>>> {
-        loop {
-            match mem::replace(&mut self.state, StreamState::Corrupted) {
-                StreamState::Pending => {
-                    if self.read_buffer.len() < 22 {
-                        self.state = StreamState::Pending;
-                        return None;
-                    }
+    /// Clear [`LocoStream::read_buffer`] and return to [`StreamState::Pending`]
+    ///
+    /// Only call after tearing down underlying transport, as partially received command is discarded.
+    pub fn reset(&mut self) {
+        self.state = StreamState::Pending;
+        self.deferred_error = None;
+        self.read_buffer.clear();
+    }
+
+    /// Try reading single [`Command`] from [`LocoStream::read_buffer`]
+    ///
+    /// Stream becomes [`StreamState::Corrupted`] if it returns error and cannot continue
+    pub fn read(&mut self) -> Result<Option<Command<Box<[u8]>>>, StreamError> {
+        Ok(self.read_raw()?.map(|(header, data)| Command {
+            header,
+            data: data.collect(),
+        }))
+    }
+
+    /// Try reading single [`Command`] from [`LocoStream::read_buffer`], storing its data into `data_buf`
+    ///
+    /// `data_buf` is cleared and filled only if complete command was read,
+    /// so same buffer can be reused across calls
+    pub fn read_into(&mut self, data_buf: &mut Vec<u8>) -> Result<Option<Header>, StreamError> {
+        Ok(self.read_raw()?.map(|(header, data)| {
+            data_buf.clear();
+            data_buf.extend(data);
+
+            header
+        }))
+    }
+
+    /// Try reading single [`Command`] from [`LocoStream::read_buffer`] and match it against `tracker`
+    ///
+    /// Returns whether command is response of request registered in `tracker` along with command
+    pub fn read_tracked(
+        &mut self,
+        tracker: &mut RequestTracker,
+    ) -> Result<Option<(bool, BoxedCommand)>, StreamError> {
+        Ok(self
+            .read()?
+            .map(|command| (tracker.take(command.header.id), command)))
+    }
+
+    /// Try reading [`RawHeader`] of next [`Command`] from [`LocoStream::read_buffer`] without reading its data
+    ///
+    /// Header is kept until its data is read by following read call,
+    /// so it can be used to peek next command.
+    pub fn read_header(&mut self) -> Result<Option<&RawHeader>, StreamError> {
+        if let StreamState::Pending = self.state {
+            if self.read_buffer.len() < HEAD_SIZE {
+                return Ok(None);
+            }
+
+            self.state = StreamState::Corrupted;
+
+            let raw_header = {
+                let buf = self
+                    .read_buffer
+                    .drain(..HEAD_SIZE)
+                    .collect::<ArrayVec<u8, HEAD_SIZE>>();
+
+                codec::bincode_options()
+                    .deserialize::<RawHeader>(&buf)
+                    .map_err(StreamError::Header)?
+            };
+
+            if raw_header.data_size > self.max_data_size {
+                return Err(StreamError::DataTooLarge {
+                    size: raw_header.data_size,
+                    max: self.max_data_size,
+                });
+            }
+
+            self.state = StreamState::Header(raw_header);
+        }
+
+        match self.state {
+            StreamState::Header(ref raw_header) => Ok(Some(raw_header)),
+            _ => Err(self.deferred_error.take().unwrap_or(StreamError::Corrupted)),
+        }
+    }
+
+    fn read_raw(&mut self) -> Result<Option<(Header, Drain<'_, u8>)>, StreamError> {
+        let data_size = match self.read_header()? {
+            Some(raw_header) => raw_header.data_size as usize,
+            None => return Ok(None),
+        };
+
+        if self.read_buffer.len() < data_size {
+            return Ok(None);
+        }
 
-                    let raw_header = {
-                        let buf = self.read_buffer.drain(..22).collect::<ArrayVec<u8, 22>>();
+        let StreamState::Header(raw_header) = mem::replace(&mut self.state, StreamState::Pending)
+        else {
+            unreachable!()
+        };
 
-                        bincode::deserialize::<RawHeader>(&buf).unwrap()
-                    };
+        Ok(Some((
+            raw_header.header,
+            self.read_buffer.drain(..data_size),
+        )))
+    }
+
+    /// Create iterator reading every complete [`Command`] from [`LocoStream::read_buffer`]
+    ///
+    /// Iterator ends after first error. Partial command is kept in buffer.
+    pub fn drain(&mut self) -> LocoStreamDrain<'_> {
+        LocoStreamDrain {
+            stream: self,
+            done: false,
+        }
+    }
 
-                    self.state = StreamState::Header(raw_header);
+    /// Read at most `n` [`Command`]s from [`LocoStream::read_buffer`]
+    ///
+    /// Returns read commands and whether more complete commands remain in buffer.
+    /// Commands read before an error are returned and the error is returned by next read call.
+    pub fn drain_up_to(&mut self, n: usize) -> Result<(Vec<BoxedCommand>, bool), StreamError> {
+        let mut commands = Vec::new();
+        let mut error = None;
+        for res in self.drain().take(n) {
+            match res {
+                Ok(command) => commands.push(command),
+                Err(err) => {
+                    error = Some(err);
+                    break;
                 }
+            }
+        }
+
+        if let Some(err) = error {
+            if commands.is_empty() {
+                return Err(err);
+            }
+
+            self.deferred_error = Some(err);
+            return Ok((commands, false));
+        }
 
-                StreamState::Header(raw_header) => {
-                    if self.read_buffer.len() < raw_header.data_size as usize {
-                        self.state = StreamState::Header(raw_header);
-                        return None;
-                    }
-
-                    let data = self
-                        .read_buffer
-                        .drain(..raw_header.data_size as usize)
-                        .collect::<Box<[u8]>>();
-
-                    self.state = StreamState::Pending;
-                    return Some(Command {
-                        header: raw_header.header,
-                        data,
-                    });
+        let remaining = commands.len() == n && self.has_command();
+        Ok((commands, remaining))
+    }
+
+    fn has_command(&self) -> bool {
+        match self.state {
+            StreamState::Pending => {
+                if self.read_buffer.len() < HEAD_SIZE {
+                    return false;
                 }
 
-                StreamState::Corrupted => unreachable!(),
+                let data_size = u32::from_le_bytes([
+                    self.read_buffer[HEADER_SIZE],
+                    self.read_buffer[HEADER_SIZE + 1],
+                    self.read_buffer[HEADER_SIZE + 2],
+                    self.read_buffer[HEADER_SIZE + 3],
+                ]);
+
+                self.read_buffer.len() - HEAD_SIZE >= data_size as usize
             }
+
+            StreamState::Header(ref raw_header) => {
+                self.read_buffer.len() >= raw_header.data_size as usize
+            }
+
+            StreamState::Corrupted => false,
         }
     }
 }
@@ -124,6 +303,134 @@ impl Default for LocoStream {
     }
 }
 
+#[cfg(feature = "std")]
+impl LocoStream {
+    /// Read available bytes from `reader` once into [`LocoStream::read_buffer`]
+    ///
+    /// Never fills buffer past single maximum sized command. Parse buffered commands before feeding again.
+    ///
+    /// Returns number of bytes read. `0` means `reader` reached EOF or buffer is full.
+    pub fn feed<R: std::io::Read>(&mut self, reader: &mut R) -> std::io::Result<usize> {
+        let mut buf = [0_u8; 2048];
+
+        // Header is not counted once parsed
+        let head_size = match self.state {
+            StreamState::Header(_) => 0,
+            _ => HEAD_SIZE,
+        };
+
+        let limit = head_size
+            .saturating_add(self.max_data_size as usize)
+            .saturating_sub(self.read_buffer.len())
+            .min(buf.len());
+
+        let read = reader.read(&mut buf[..limit])?;
+        self.read_buffer.extend(&buf[..read]);
+
+        Ok(read)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Tracks ids of sent requests to match incoming responses
+pub struct RequestTracker {
+    ids: BTreeSet<u32>,
+}
+
+impl RequestTracker {
+    /// Create new [`RequestTracker`]
+    pub const fn new() -> Self {
+        Self {
+            ids: BTreeSet::new(),
+        }
+    }
+
+    /// Register id of sent request
+    pub fn register(&mut self, id: u32) {
+        self.ids.insert(id);
+    }
+
+    /// Remove id from tracker
+    ///
+    /// Returns `true` if id was registered
+    pub fn take(&mut self, id: u32) -> bool {
+        self.ids.remove(&id)
+    }
+
+    /// Number of requests waiting for response
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[derive(Debug)]
+/// Iterator returned by [`LocoStream::drain`]
+pub struct LocoStreamDrain<'a> {
+    stream: &'a mut LocoStream,
+    done: bool,
+}
+
+impl Iterator for LocoStreamDrain<'_> {
+    type Item = Result<BoxedCommand, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let res = self.stream.read().transpose();
+        if !matches!(res, Some(Ok(_))) {
+            self.done = true;
+        }
+
+        res
+    }
+}
+
+impl FusedIterator for LocoStreamDrain<'_> {}
+
+/// Default maximum data size of single command
+pub const DEFAULT_MAX_DATA_SIZE: u32 = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+#[non_exhaustive]
+/// [`LocoStream`] read error
+pub enum StreamError {
+    /// Command header is invalid
+    Header(bincode::Error),
+
+    /// Command data size exceeds maximum size
+    DataTooLarge { size: u32, max: u32 },
+
+    /// Stream is corrupted and cannot continue
+    Corrupted,
+}
+
+impl Display for StreamError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StreamError::Header(err) => write!(f, "invalid command header: {err}"),
+            StreamError::DataTooLarge { size, max } => {
+                write!(f, "command data size {size} exceeds maximum {max}")
+            }
+            StreamError::Corrupted => write!(f, "stream is corrupted"),
+        }
+    }
+}
+
+impl Error for StreamError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StreamError::Header(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum StreamState {
     /// Stream is waiting for packet
@@ -0,0 +1,74 @@
+/*
+ * Created on Thu Oct 15 2026
+ *
+ * Copyright (c) storycraft. Licensed under the MIT Licence.
+ */
+
+use bincode::Options;
+
+use super::{
+    client::{RawHeader, StreamError, DEFAULT_MAX_DATA_SIZE},
+    BoxedCommand, Command, HEAD_SIZE,
+};
+
+/// Bincode configuration used for every wire structure
+///
+/// Integers are encoded as fixed size little endian and trailing bytes are allowed,
+/// which pins wire format regardless of bincode defaults.
+pub fn bincode_options() -> impl Options + Copy {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+        .allow_trailing_bytes()
+}
+
+/// Decode single [`Command`] from start of `buf`
+///
+/// Returns decoded command and number of consumed bytes, or `None` if `buf` doesn't contain complete command yet.
+/// Returns [`StreamError::DataTooLarge`] if data size exceeds [`DEFAULT_MAX_DATA_SIZE`].
+pub fn decode_command(buf: &[u8]) -> Result<Option<(BoxedCommand, usize)>, StreamError> {
+    decode_command_with_max(buf, DEFAULT_MAX_DATA_SIZE)
+}
+
+/// Decode single [`Command`] from start of `buf` with given maximum command data size
+///
+/// Same as [`decode_command`], except data size is limited to `max_data_size`.
+/// Returns [`StreamError::DataTooLarge`] if frame size doesn't fit in `usize`.
+pub fn decode_command_with_max(
+    buf: &[u8],
+    max_data_size: u32,
+) -> Result<Option<(BoxedCommand, usize)>, StreamError> {
+    if buf.len() < HEAD_SIZE {
+        return Ok(None);
+    }
+
+    let raw_header = bincode_options()
+        .deserialize::<RawHeader>(&buf[..HEAD_SIZE])
+        .map_err(StreamError::Header)?;
+
+    if raw_header.data_size > max_data_size {
+        return Err(StreamError::DataTooLarge {
+            size: raw_header.data_size,
+            max: max_data_size,
+        });
+    }
+
+    // Frame not addressable on 32-bit targets can never complete
+    let Some(end) = HEAD_SIZE.checked_add(raw_header.data_size as usize) else {
+        return Err(StreamError::DataTooLarge {
+            size: raw_header.data_size,
+            max: max_data_size,
+        });
+    };
+    if buf.len() < end {
+        return Ok(None);
+    }
+
+    Ok(Some((
+        Command {
+            header: raw_header.header,
+            data: buf[HEAD_SIZE..end].into(),
+        },
+        end,
+    )))
+}
@@ -0,0 +1,117 @@
+/*
+ * Created on Thu Oct 15 2026
+ *
+ * Copyright (c) storycraft. Licensed under the MIT Licence.
+ */
+
+use super::Method;
+
+macro_rules! known_methods {
+    ($($(#[$meta:meta])* $name:ident => $string:literal,)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[non_exhaustive]
+        /// Known loco methods
+        pub enum KnownMethod {
+            $($(#[$meta])* $name,)*
+        }
+
+        impl KnownMethod {
+            /// All known methods
+            pub const ALL: &'static [KnownMethod] = &[$(KnownMethod::$name,)*];
+
+            /// Method string
+            pub const fn as_str(self) -> &'static str {
+                match self {
+                    $(KnownMethod::$name => $string,)*
+                }
+            }
+        }
+    };
+}
+
+known_methods! {
+    /// Request loco server list
+    GetConf => "GETCONF",
+    /// Request loco server address
+    CheckIn => "CHECKIN",
+    /// Request loco server address without login
+    BuyCs => "BUYCS",
+    /// Login and request chatroom list
+    LoginList => "LOGINLIST",
+    /// Request more chatroom list
+    LChatList => "LCHATLIST",
+    /// Keep-alive
+    Ping => "PING",
+    /// Server change notification
+    ChangeSvr => "CHANGESVR",
+    /// Session kicked out
+    Kickout => "KICKOUT",
+    /// Send chat
+    Write => "WRITE",
+    /// Forward chat
+    Forward => "FORWARD",
+    /// Chat received
+    Msg => "MSG",
+    /// Delete chat
+    DeleteMsg => "DELETEMSG",
+    /// Sync chat logs
+    SyncMsg => "SYNCMSG",
+    /// Mark chat as read
+    NotiRead => "NOTIREAD",
+    /// Chat read by other user
+    DecUnread => "DECUNREAD",
+    /// Request chatroom info
+    ChatInfo => "CHATINFO",
+    /// Open chatroom
+    ChatOnRoom => "CHATONROOM",
+    /// Request chatroom members
+    GetMem => "GETMEM",
+    /// Request chatroom member info
+    Member => "MEMBER",
+    /// User joined chatroom
+    NewMem => "NEWMEM",
+    /// User left chatroom
+    DelMem => "DELMEM",
+    /// Leave chatroom
+    Leave => "LEAVE",
+    /// Left chatroom
+    Left => "LEFT",
+    /// Request media upload or download host
+    GetTrailer => "GETTRAILER",
+}
+
+impl KnownMethod {
+    /// Convert to [`Method`]
+    pub const fn as_method(self) -> Method {
+        Method::from_bytes(pad(self.as_str()))
+    }
+}
+
+impl Method {
+    /// Convert to [`KnownMethod`] if method is known
+    pub fn to_known(&self) -> Option<KnownMethod> {
+        KnownMethod::ALL
+            .iter()
+            .copied()
+            .find(|known| self.eq_str(known.as_str()))
+    }
+}
+
+impl From<KnownMethod> for Method {
+    fn from(method: KnownMethod) -> Self {
+        method.as_method()
+    }
+}
+
+const fn pad(string: &str) -> [u8; 11] {
+    let bytes = string.as_bytes();
+
+    let mut buf = [0_u8; 11];
+    let mut i = 0;
+    while i < bytes.len() {
+        buf[i] = bytes[i];
+        i += 1;
+    }
+
+    buf
+}
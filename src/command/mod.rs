@@ -4,18 +4,112 @@
  * Copyright (c) storycraft. Licensed under the MIT Licence.
  */
 
-use core::{fmt::Debug, ops::Deref};
+use core::{
+    error::Error,
+    fmt::{Debug, Display},
+    ops::Deref,
+    str::Utf8Error,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
-use alloc::boxed::Box;
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
 use serde::{
     de::{self, Unexpected, Visitor},
     Deserialize, Serialize,
 };
 
+use known::KnownMethod;
+
 pub mod client;
+pub mod codec;
+pub mod known;
+pub mod typed;
+
+/// Size of serialized [`Header`]
+pub const HEADER_SIZE: usize = 18;
+
+/// Size of serialized [`Header`] followed by data size
+pub const HEAD_SIZE: usize = HEADER_SIZE + 4;
+
+/// Create response for `PING` command
+///
+/// Response has same id and data type of `ping` and has empty data
+pub fn pong_response<T: ?Sized>(ping: &Command<T>) -> BoxedCommand {
+    Command {
+        header: Header {
+            id: ping.header.id,
+            status: Status::SUCCESS,
+            method: KnownMethod::Ping.into(),
+            data_type: ping.header.data_type,
+        },
+        data: Box::new([]),
+    }
+}
+
+/// Create keep-alive `PING` request with id allocated from `id_alloc`
+pub fn ping_request(id_alloc: &IdAllocator) -> BoxedCommand {
+    Command {
+        header: Header {
+            id: id_alloc.next(),
+            status: Status::SUCCESS,
+            method: KnownMethod::Ping.into(),
+            data_type: DataType::BSON,
+        },
+        data: Box::new([]),
+    }
+}
+
+/// Parse every complete [`Command`] from `buf`
+///
+/// Returns parsed commands and number of trailing bytes forming incomplete command.
+/// Parsing stops at invalid header or data size exceeding [`client::DEFAULT_MAX_DATA_SIZE`],
+/// which is returned as [`FrameError`] along with commands parsed before it.
+pub fn parse_all(buf: &[u8]) -> (Vec<BoxedCommand>, Result<usize, FrameError>) {
+    let mut commands = Vec::new();
+
+    let mut offset = 0;
+    loop {
+        match codec::decode_command(&buf[offset..]) {
+            Ok(Some((command, size))) => {
+                commands.push(command);
+                offset += size;
+            }
+
+            Ok(None) => return (commands, Ok(buf.len() - offset)),
+
+            Err(error) => return (commands, Err(FrameError { offset, error })),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Invalid command frame found by [`parse_all`]
+pub struct FrameError {
+    /// Offset of invalid frame in input
+    pub offset: usize,
+
+    /// Reason frame is invalid
+    pub error: client::StreamError,
+}
+
+impl Display for FrameError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "invalid frame at offset {}: {}", self.offset, self.error)
+    }
+}
+
+impl Error for FrameError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
 
 #[derive(Clone, PartialEq, Eq)]
 /// 11 bytes string padded with `\0`
+///
+/// Dereferencing is lossy. Method created from non utf-8 bytes dereferences to its longest valid utf-8 prefix,
+/// which may equal a string the method itself doesn't. Use [`Method::as_str`] for checked access
+/// and [`Method::eq_str`] or `==` for comparison.
 pub struct Method {
     len: usize,
     buf: [u8; 11],
@@ -38,6 +132,49 @@ impl Method {
         Some(Self { len, buf })
     }
 
+    /// Create new [`Method`] from raw 11 bytes padded with `\0`
+    ///
+    /// Length is recorded by trimming trailing `\0`
+    pub const fn from_bytes(buf: [u8; 11]) -> Self {
+        let mut len = buf.len();
+        while len > 0 && buf[len - 1] == 0 {
+            len -= 1;
+        }
+
+        Self { len, buf }
+    }
+
+    /// Raw 11 bytes padded with `\0`
+    pub const fn as_bytes(&self) -> &[u8; 11] {
+        &self.buf
+    }
+
+    /// Try converting method to string
+    ///
+    /// Returns [`Utf8Error`] if method is not valid utf-8
+    pub fn as_str(&self) -> Result<&str, Utf8Error> {
+        core::str::from_utf8(&self.buf[..self.len])
+    }
+
+    /// Compare method with given string, usable in const context
+    pub const fn eq_str(&self, string: &str) -> bool {
+        let bytes = string.as_bytes();
+        if bytes.len() != self.len {
+            return false;
+        }
+
+        let mut i = 0;
+        while i < self.len {
+            if self.buf[i] != bytes[i] {
+                return false;
+            }
+
+            i += 1;
+        }
+
+        true
+    }
+
     pub const fn is_empty(&self) -> bool {
         self.len == 0
     }
@@ -49,7 +186,33 @@ impl Method {
 
 impl Debug for Method {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_tuple("Method").field(&self.deref()).finish()
+        match self.as_str() {
+            Ok(string) => f.debug_tuple("Method").field(&string).finish(),
+
+            // Print raw bytes so invalid method is not mistaken for its utf-8 prefix
+            Err(_) => f
+                .debug_tuple("Method")
+                .field(&&self.buf[..self.len])
+                .finish(),
+        }
+    }
+}
+
+impl PartialEq<str> for Method {
+    fn eq(&self, other: &str) -> bool {
+        self.eq_str(other)
+    }
+}
+
+impl PartialEq<&str> for Method {
+    fn eq(&self, other: &&str) -> bool {
+        self.eq_str(other)
+    }
+}
+
+impl PartialEq<Method> for str {
+    fn eq(&self, other: &Method) -> bool {
+        other.eq_str(self)
     }
 }
 
@@ -57,7 +220,12 @@ impl Deref for Method {
     type Target = str;
 
     fn deref(&self) -> &Self::Target {
-        core::str::from_utf8(&self.buf[..self.len]).unwrap()
+        match self.as_str() {
+            Ok(string) => string,
+
+            // Expose valid utf-8 prefix only for method created from raw bytes
+            Err(err) => core::str::from_utf8(&self.buf[..err.valid_up_to()]).unwrap_or_default(),
+        }
     }
 }
 
@@ -104,17 +272,14 @@ impl<'de> Deserialize<'de> for Method {
                     ))?;
                 }
 
-                let len = core::str::from_utf8(&buf)
-                    .map_err(|_| {
-                        de::Error::invalid_type(
-                            Unexpected::Bytes(&buf),
-                            &"a valid utf-8 array was expected",
-                        )
-                    })?
-                    .trim_matches(char::from(0))
-                    .len();
-
-                Ok(Method { len, buf })
+                core::str::from_utf8(&buf).map_err(|_| {
+                    de::Error::invalid_type(
+                        Unexpected::Bytes(&buf),
+                        &"a valid utf-8 array was expected",
+                    )
+                })?;
+
+                Ok(Method::from_bytes(buf))
             }
         }
 
@@ -125,9 +290,50 @@ impl<'de> Deserialize<'de> for Method {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Header {
     pub id: u32,
-    pub status: u16,
+    pub status: Status,
     pub method: Method,
-    pub data_type: u8,
+    pub data_type: DataType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+/// Command status. Negative status indicates error.
+pub struct Status(pub i16);
+
+impl Status {
+    pub const SUCCESS: Status = Status(0);
+
+    pub const fn is_success(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn is_error(self) -> bool {
+        self.0 < 0
+    }
+}
+
+impl From<i16> for Status {
+    fn from(status: i16) -> Self {
+        Self(status)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+/// Command data format
+///
+/// Only [`DataType::BSON`] has known wire value. Other formats can be used through raw value.
+pub struct DataType(pub u8);
+
+impl DataType {
+    /// Bson document
+    pub const BSON: DataType = DataType(0);
+}
+
+impl From<u8> for DataType {
+    fn from(data_type: u8) -> Self {
+        Self(data_type)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -136,4 +342,254 @@ pub struct Command<T: ?Sized> {
     pub data: T,
 }
 
+impl Command<Box<[u8]>> {
+    /// Create new [`Command`] from header fields
+    ///
+    /// Returns [`MethodTooLong`] if method is longer than 11 bytes
+    pub fn new(
+        id: u32,
+        status: i16,
+        method: &str,
+        data_type: u8,
+        data: impl Into<Box<[u8]>>,
+    ) -> Result<Self, MethodTooLong> {
+        Ok(Self {
+            header: Header {
+                id,
+                status: Status(status),
+                method: Method::new(method).ok_or(MethodTooLong)?,
+                data_type: DataType(data_type),
+            },
+            data: data.into(),
+        })
+    }
+}
+
+impl<T: ?Sized> Command<T> {
+    /// Check if command is keep-alive `PING` command
+    pub const fn is_ping(&self) -> bool {
+        self.header.method.eq_str(KnownMethod::Ping.as_str())
+    }
+}
+
+impl<'a> Command<&'a [u8]> {
+    /// Create new [`Command`] borrowing `data`
+    ///
+    /// Useful for forwarding command without copying data
+    pub const fn borrowed(header: Header, data: &'a [u8]) -> Self {
+        Self { header, data }
+    }
+}
+
+impl<T: AsRef<[u8]>> Command<T> {
+    /// Check if command is valid request
+    ///
+    /// Request must have non empty method and data that fits in data size
+    pub fn validate_request(&self) -> Result<(), CommandError> {
+        if self.header.method.is_empty() {
+            return Err(CommandError::EmptyMethod);
+        }
+
+        let len = self.data.as_ref().len();
+        if u32::try_from(len).is_err() {
+            return Err(CommandError::DataTooLarge(len));
+        }
+
+        Ok(())
+    }
+}
+
 pub type BoxedCommand = Command<Box<[u8]>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+/// Invalid command error
+pub enum CommandError {
+    /// Method is empty
+    EmptyMethod,
+
+    /// Data size doesn't fit in u32
+    DataTooLarge(usize),
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CommandError::EmptyMethod => write!(f, "method is empty"),
+            CommandError::DataTooLarge(size) => write!(f, "data size {size} exceeds u32"),
+        }
+    }
+}
+
+impl Error for CommandError {}
+
+#[derive(Debug)]
+/// Atomic monotonically increasing command id allocator
+pub struct IdAllocator {
+    next: AtomicU32,
+    skip_zero: bool,
+}
+
+impl IdAllocator {
+    /// Create new [`IdAllocator`] starting from `start`
+    pub const fn new(start: u32) -> Self {
+        Self {
+            next: AtomicU32::new(start),
+            skip_zero: false,
+        }
+    }
+
+    /// Set whether id `0` is skipped, including after wrapping around
+    pub const fn skip_zero(mut self, skip_zero: bool) -> Self {
+        self.skip_zero = skip_zero;
+        self
+    }
+
+    /// Allocate next id
+    pub fn next(&self) -> u32 {
+        loop {
+            let id = self.next.fetch_add(1, Ordering::Relaxed);
+
+            if !self.skip_zero || id != 0 {
+                return id;
+            }
+        }
+    }
+}
+
+impl Default for IdAllocator {
+    fn default() -> Self {
+        Self::new(1).skip_zero(true)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Stores sent [`Command`]s by id until their response arrives
+///
+/// Commands left pending can be sent again after reconnecting.
+pub struct ResendQueue<T> {
+    commands: BTreeMap<u32, Command<T>>,
+}
+
+impl<T> ResendQueue<T> {
+    /// Create new [`ResendQueue`]
+    pub const fn new() -> Self {
+        Self {
+            commands: BTreeMap::new(),
+        }
+    }
+
+    /// Store sent command
+    ///
+    /// Returns previously stored command with same id
+    pub fn push(&mut self, command: Command<T>) -> Option<Command<T>> {
+        self.commands.insert(command.header.id, command)
+    }
+
+    /// Remove command acknowledged by response with given id
+    pub fn ack(&mut self, id: u32) -> Option<Command<T>> {
+        self.commands.remove(&id)
+    }
+
+    /// Iterate unacknowledged commands in id order
+    pub fn pending(&self) -> impl Iterator<Item = &Command<T>> {
+        self.commands.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+impl<T> Default for ResendQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type Handler<'a, R> = Box<dyn FnMut(BoxedCommand) -> R + 'a>;
+
+/// Dispatches [`Command`]s to handlers registered by method
+pub struct Router<'a, R = ()> {
+    handlers: BTreeMap<[u8; 11], Handler<'a, R>>,
+    fallback: Option<Handler<'a, R>>,
+}
+
+impl<'a, R> Router<'a, R> {
+    /// Create new empty [`Router`]
+    pub const fn new() -> Self {
+        Self {
+            handlers: BTreeMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// Register handler for `method`, replacing previous one
+    ///
+    /// Returns [`MethodTooLong`] if method is longer than 11 bytes
+    pub fn on(
+        &mut self,
+        method: &str,
+        handler: impl FnMut(BoxedCommand) -> R + 'a,
+    ) -> Result<(), MethodTooLong> {
+        let method = Method::new(method).ok_or(MethodTooLong)?;
+        self.handlers.insert(method.buf, Box::new(handler));
+
+        Ok(())
+    }
+
+    /// Set handler called for commands without registered handler
+    pub fn fallback(&mut self, handler: impl FnMut(BoxedCommand) -> R + 'a) {
+        self.fallback = Some(Box::new(handler));
+    }
+
+    /// Call handler registered for method of `command`
+    ///
+    /// Returns `None` if neither matching handler nor fallback handler exists
+    pub fn dispatch(&mut self, command: BoxedCommand) -> Option<R> {
+        let handler = match self.handlers.get_mut(command.header.method.as_bytes()) {
+            Some(handler) => handler,
+            None => self.fallback.as_mut()?,
+        };
+
+        Some(handler(command))
+    }
+}
+
+impl<R> Debug for Router<'_, R> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Router")
+            .field(
+                "methods",
+                &self
+                    .handlers
+                    .keys()
+                    .map(|buf| Method::from_bytes(*buf))
+                    .collect::<Vec<_>>(),
+            )
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl<R> Default for Router<'_, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Method string was longer than 11 bytes
+pub struct MethodTooLong;
+
+impl Display for MethodTooLong {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "method is longer than 11 bytes")
+    }
+}
+
+impl Error for MethodTooLong {}
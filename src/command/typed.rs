@@ -0,0 +1,75 @@
+/*
+ * Created on Thu Oct 15 2026
+ *
+ * Copyright (c) storycraft. Licensed under the MIT Licence.
+ */
+
+use alloc::vec::Vec;
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{client::LocoSink, codec, Command, Header};
+
+/// Command body serialization format
+pub trait BodyCodec {
+    type Error;
+
+    /// Serialize body into command data
+    fn encode<T: Serialize + ?Sized>(body: &T) -> Result<Vec<u8>, Self::Error>;
+
+    /// Deserialize body from command data
+    fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, Self::Error>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Bincode body codec
+pub struct BincodeCodec;
+
+impl BodyCodec for BincodeCodec {
+    type Error = bincode::Error;
+
+    fn encode<T: Serialize + ?Sized>(body: &T) -> Result<Vec<u8>, Self::Error> {
+        codec::bincode_options().serialize(body)
+    }
+
+    fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, Self::Error> {
+        codec::bincode_options().deserialize(data)
+    }
+}
+
+impl LocoSink {
+    /// Write single [`Command`] with body serialized using [`BincodeCodec`]
+    pub fn send_typed<T: Serialize + ?Sized>(
+        &mut self,
+        header: Header,
+        body: &T,
+    ) -> Result<(), bincode::Error> {
+        self.send_typed_with::<BincodeCodec, T>(header, body)
+    }
+
+    /// Write single [`Command`] with body serialized using given [`BodyCodec`]
+    pub fn send_typed_with<C: BodyCodec, T: Serialize + ?Sized>(
+        &mut self,
+        header: Header,
+        body: &T,
+    ) -> Result<(), C::Error> {
+        self.send(Command {
+            header,
+            data: C::encode(body)?,
+        });
+
+        Ok(())
+    }
+}
+
+impl<D: AsRef<[u8]>> Command<D> {
+    /// Deserialize command data using [`BincodeCodec`]
+    pub fn parse_body<T: DeserializeOwned>(&self) -> Result<T, bincode::Error> {
+        self.parse_body_with::<BincodeCodec, T>()
+    }
+
+    /// Deserialize command data using given [`BodyCodec`]
+    pub fn parse_body_with<C: BodyCodec, T: DeserializeOwned>(&self) -> Result<T, C::Error> {
+        C::decode(self.data.as_ref())
+    }
+}
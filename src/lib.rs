@@ -12,6 +12,9 @@
 #![no_std]
 extern crate alloc;
 
+#[cfg(feature = "std")]
+extern crate std;
+
 // Loco protocol implementation
 pub mod command;
 
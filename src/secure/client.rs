@@ -7,28 +7,24 @@
 pub use rsa;
 
 use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
-use core::mem;
 
-use aes::cipher::{AsyncStreamCipher, Key, KeyIvInit};
-use arrayvec::ArrayVec;
-use rand::thread_rng;
-use rsa::{Oaep, RsaPublicKey};
+use aes::{cipher::Key, Aes128};
+use rand::{CryptoRng, RngCore};
+use rsa::RsaPublicKey;
 use serde::{Deserialize, Serialize};
-use sha1::Sha1;
 
-use super::SecurePacket;
-
-type Aes128CfbEnc = cfb_mode::Encryptor<aes::Aes128>;
-type Aes128CfbDec = cfb_mode::Decryptor<aes::Aes128>;
+use super::{
+    crypto::{CryptoError, EncryptType, KeyEncryptType},
+    layer::PacketLayer,
+    HandshakePacket, SecurePacket,
+};
 
 #[derive(Debug)]
 /// IO-free client secure layer
 pub struct LocoClientSecureLayer {
-    key: Key<aes::Aes128>,
-
-    read_state: ReadState,
+    key: Key<Aes128>,
 
-    encrypt_buffer: Vec<u8>,
+    layer: PacketLayer,
 
     /// Read buffer for layer
     pub read_buffer: VecDeque<u8>,
@@ -40,115 +36,159 @@ pub struct LocoClientSecureLayer {
 impl LocoClientSecureLayer {
     /// Create new [`LocoClientSecureLayer`] with given encrypt key
     pub fn new(encrypt_key: [u8; 16]) -> Self {
+        let key = encrypt_key.into();
         Self {
-            key: encrypt_key.into(),
-
-            read_state: ReadState::Pending,
-
-            encrypt_buffer: Vec::new(),
+            key,
+            layer: PacketLayer::new(&key),
 
             read_buffer: VecDeque::new(),
             write_buffer: VecDeque::new(),
         }
     }
 
+    /// Create new [`LocoClientSecureLayer`] with encrypt key generated from `rng`
+    pub fn from_rng<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut key = [0_u8; 16];
+        rng.fill_bytes(&mut key);
+
+        Self::new(key)
+    }
+
+    /// Raw AES session key
+    ///
+    /// This is secret key protecting every packet of the session.
+    /// Anyone holding it can decrypt and forge traffic, so only persist it to secure storage.
+    pub fn key(&self) -> &[u8; 16] {
+        self.key.as_ref()
+    }
+
     pub const fn read_state(&self) -> &ReadState {
-        &self.read_state
+        &self.layer.read_state
+    }
+
+    /// Clear read and write buffer and return to [`ReadState::Pending`], keeping encrypt key
+    ///
+    /// Only call after tearing down underlying transport, as buffered data is discarded.
+    /// Handshake must be written again before sending packets over new transport.
+    pub fn reset(&mut self) {
+        self.layer.reset();
+        self.read_buffer.clear();
+        self.write_buffer.clear();
+    }
+
+    /// Set whether CRC-32 of plaintext is appended to sent packets and verified on read
+    ///
+    /// Mismatching checksum makes layer [`ReadState::Corrupted`].
+    /// Both ends must enable it and official servers don't support it. Disabled by default.
+    pub fn set_checksum(&mut self, checksum: bool) {
+        self.layer.checksum = checksum;
+    }
+
+    /// Set capacity internal encrypt buffer is shrunk to after sending larger packet
+    ///
+    /// `None` keeps allocated capacity. Defaults to [`super::DEFAULT_ENCRYPT_BUFFER_LIMIT`].
+    pub fn set_encrypt_buffer_limit(&mut self, limit: Option<usize>) {
+        self.layer.encrypt_buffer_limit = limit;
     }
 
     /// Write handshake packet to [`LocoClientSecureLayer::write_buffer`] using given public key
-    pub fn handshake(&mut self, key: &RsaPublicKey) {
-        #[derive(Serialize)]
-        struct RawHandshakeHeader {
-            encrypted_key_size: u32,
-            key_type: u32,
-            encrypt_type: u32,
-        }
+    ///
+    /// Returns number of bytes appended to [`LocoClientSecureLayer::write_buffer`]
+    #[cfg(feature = "std")]
+    pub fn handshake(&mut self, key: &RsaPublicKey) -> Result<usize, CryptoError> {
+        self.handshake_with(key, KeyEncryptType::default(), EncryptType::default())
+    }
+
+    /// Write handshake packet to [`LocoClientSecureLayer::write_buffer`] using given public key and encryption types
+    ///
+    /// Returns number of bytes appended to [`LocoClientSecureLayer::write_buffer`]
+    #[cfg(feature = "std")]
+    pub fn handshake_with(
+        &mut self,
+        key: &RsaPublicKey,
+        key_type: KeyEncryptType,
+        encrypt_type: EncryptType,
+    ) -> Result<usize, CryptoError> {
+        self.handshake_with_rng(key, key_type, encrypt_type, &mut rand::thread_rng())
+    }
+
+    /// Write handshake packet to [`LocoClientSecureLayer::write_buffer`] using given public key, encryption types and padding rng
+    ///
+    /// Returns number of bytes appended to [`LocoClientSecureLayer::write_buffer`]
+    pub fn handshake_with_rng<R: RngCore + CryptoRng>(
+        &mut self,
+        key: &RsaPublicKey,
+        key_type: KeyEncryptType,
+        encrypt_type: EncryptType,
+        rng: &mut R,
+    ) -> Result<usize, CryptoError> {
+        let encrypted_key = key.encrypt(rng, key_type.padding(), self.key.as_slice())?;
 
-        let encrypted_key = key
-            .encrypt(
-                &mut thread_rng(),
-                Oaep::new_with_mgf_hash::<Sha1, Sha1>(),
-                self.key.as_slice(),
-            )
-            .unwrap();
-
-        bincode::serialize_into(
-            &mut self.write_buffer,
-            &RawHandshakeHeader {
-                encrypted_key_size: encrypted_key.len() as u32,
-                key_type: 15,    // RSA OAEP SHA1 MGF1 SHA1
-                encrypt_type: 2, // AES_CFB128 NOPADDING
-            },
-        )
-        .unwrap();
-
-        self.write_buffer.extend(encrypted_key);
+        let start = self.write_buffer.len();
+
+        HandshakePacket::new(key_type.into(), encrypt_type.into(), encrypted_key)
+            .encode(&mut self.write_buffer);
+
+        Ok(self.write_buffer.len() - start)
     }
 
     /// Try to read single [`SecurePacket`] from [`LocoClientSecureLayer::read_buffer`]
+    ///
+    /// Layer becomes [`ReadState::Corrupted`] on malformed packet and returns `None` afterwards
     pub fn read(&mut self) -> Option<SecurePacket<Box<[u8]>>> {
-        loop {
-            match mem::replace(&mut self.read_state, ReadState::Corrupted) {
-                ReadState::Pending => {
-                    if self.read_buffer.len() < 20 {
-                        self.read_state = ReadState::Pending;
-                        return None;
-                    }
-
-                    let raw_header = {
-                        let buf = self.read_buffer.drain(..20).collect::<ArrayVec<u8, 20>>();
-
-                        bincode::deserialize::<RawHeader>(&buf).unwrap()
-                    };
-
-                    self.read_state = ReadState::Header(raw_header);
-                }
-
-                ReadState::Header(raw_header) => {
-                    let size = raw_header.size as usize - 16;
-
-                    if self.read_buffer.len() < size {
-                        self.read_state = ReadState::Header(raw_header);
-                        return None;
-                    }
-
-                    let mut data = self.read_buffer.drain(..size).collect::<Box<[u8]>>();
-                    Aes128CfbDec::new(&self.key, &raw_header.iv.into()).decrypt(&mut data);
-
-                    self.read_state = ReadState::Pending;
-                    return Some(SecurePacket {
-                        iv: raw_header.iv,
-                        data,
-                    });
-                }
-
-                ReadState::Corrupted => unreachable!(),
+        self.layer.read(&mut self.read_buffer)
+    }
+
+    /// Read at most `n` [`SecurePacket`]s from [`LocoClientSecureLayer::read_buffer`]
+    ///
+    /// Returns read packets and whether more complete packets remain in buffer
+    pub fn drain_up_to(&mut self, n: usize) -> (Vec<SecurePacket<Box<[u8]>>>, bool) {
+        let mut packets = Vec::new();
+
+        while packets.len() < n {
+            match self.read() {
+                Some(packet) => packets.push(packet),
+                None => return (packets, false),
             }
         }
+
+        (packets, self.layer.has_packet(&self.read_buffer))
     }
 
     /// Write single [`SecurePacket`] to [`LocoClientSecureLayer::write_buffer`]
     pub fn send(&mut self, packet: SecurePacket<impl AsRef<[u8]>>) {
-        let encrypted_data = {
-            let data = packet.data.as_ref();
+        self.layer.send(&mut self.write_buffer, packet);
+    }
+}
 
-            self.encrypt_buffer.extend(data);
-            Aes128CfbEnc::new(&self.key, &packet.iv.into()).encrypt(&mut self.encrypt_buffer);
+#[cfg(feature = "std")]
+impl LocoClientSecureLayer {
+    /// Perform single blocking IO cycle on `io`
+    ///
+    /// Writes [`LocoClientSecureLayer::write_buffer`] to `io`,
+    /// then reads available bytes once into [`LocoClientSecureLayer::read_buffer`].
+    /// Call it in a loop to drive the layer.
+    ///
+    /// Returns number of bytes read. `0` means `io` reached EOF.
+    pub fn pump<S: std::io::Read + std::io::Write>(
+        &mut self,
+        io: &mut S,
+    ) -> std::io::Result<usize> {
+        while !self.write_buffer.is_empty() {
+            let written = io.write(self.write_buffer.as_slices().0)?;
+            if written == 0 {
+                return Err(std::io::ErrorKind::WriteZero.into());
+            }
 
-            &mut self.encrypt_buffer
-        };
+            self.write_buffer.drain(..written);
+        }
+        io.flush()?;
 
-        bincode::serialize_into(
-            &mut self.write_buffer,
-            &RawHeader {
-                size: 16 + encrypted_data.len() as u32,
-                iv: packet.iv,
-            },
-        )
-        .unwrap();
+        let mut buf = [0_u8; 2048];
+        let read = io.read(&mut buf)?;
+        self.read_buffer.extend(&buf[..read]);
 
-        self.write_buffer.extend(encrypted_data.drain(..));
+        Ok(read)
     }
 }
 
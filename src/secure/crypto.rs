@@ -0,0 +1,118 @@
+/*
+ * Created on Thu Oct 15 2026
+ *
+ * Copyright (c) storycraft. Licensed under the MIT Licence.
+ */
+
+use core::{error::Error, fmt::Display};
+
+use rsa::Oaep;
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+#[repr(u32)]
+/// Handshake key encryption type
+pub enum KeyEncryptType {
+    /// Legacy RSA OAEP SHA1 MGF1 SHA1
+    RsaOaepSha1Mgf1Sha1Old = 12,
+
+    /// RSA OAEP SHA1 MGF1 SHA1
+    #[default]
+    RsaOaepSha1Mgf1Sha1 = 15,
+}
+
+impl KeyEncryptType {
+    /// RSA padding scheme used by this type
+    pub(crate) fn padding(self) -> Oaep {
+        match self {
+            KeyEncryptType::RsaOaepSha1Mgf1Sha1Old | KeyEncryptType::RsaOaepSha1Mgf1Sha1 => {
+                Oaep::new_with_mgf_hash::<Sha1, Sha1>()
+            }
+        }
+    }
+}
+
+impl From<KeyEncryptType> for u32 {
+    fn from(ty: KeyEncryptType) -> Self {
+        ty as u32
+    }
+}
+
+impl TryFrom<u32> for KeyEncryptType {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            12 => Ok(KeyEncryptType::RsaOaepSha1Mgf1Sha1Old),
+            15 => Ok(KeyEncryptType::RsaOaepSha1Mgf1Sha1),
+            _ => Err(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+#[repr(u32)]
+/// Data encryption type
+pub enum EncryptType {
+    /// AES CFB128 NOPADDING
+    #[default]
+    AesCfb128 = 2,
+}
+
+impl From<EncryptType> for u32 {
+    fn from(ty: EncryptType) -> Self {
+        ty as u32
+    }
+}
+
+impl TryFrom<u32> for EncryptType {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            2 => Ok(EncryptType::AesCfb128),
+            _ => Err(value),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+/// Secure layer crypto error
+pub enum CryptoError {
+    /// RSA operation failed
+    Rsa(rsa::Error),
+}
+
+impl Display for CryptoError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CryptoError::Rsa(err) => write!(f, "rsa error: {err}"),
+        }
+    }
+}
+
+impl Error for CryptoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CryptoError::Rsa(err) => Some(err),
+        }
+    }
+}
+
+impl From<rsa::Error> for CryptoError {
+    fn from(err: rsa::Error) -> Self {
+        Self::Rsa(err)
+    }
+}
+
+/// Compare two byte slices in constant time
+///
+/// Time taken depends only on length of slices, not their contents.
+/// Slices with different length are never equal.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
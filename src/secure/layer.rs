@@ -0,0 +1,185 @@
+/*
+ * Created on Thu Oct 15 2026
+ *
+ * Copyright (c) storycraft. Licensed under the MIT Licence.
+ */
+
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use core::mem;
+
+use aes::{
+    cipher::{AsyncStreamCipher, InnerIvInit, Key, KeyInit},
+    Aes128,
+};
+use arrayvec::ArrayVec;
+use bincode::Options;
+
+use crate::command::codec::bincode_options;
+
+use super::{
+    checksum,
+    client::{RawHeader, ReadState},
+    peek_size_prefix, SecurePacket, DEFAULT_ENCRYPT_BUFFER_LIMIT, SECURE_HEADER_SIZE,
+    SECURE_HEAD_SIZE, SECURE_SIZE_PREFIX,
+};
+
+type Aes128CfbEnc = cfb_mode::Encryptor<Aes128>;
+type Aes128CfbDec = cfb_mode::Decryptor<Aes128>;
+
+#[derive(Debug)]
+/// Secure packet framing state shared by client and server layer
+pub(crate) struct PacketLayer {
+    /// Expanded key schedule reused for every packet
+    cipher: Aes128,
+
+    pub read_state: ReadState,
+
+    encrypt_buffer: Vec<u8>,
+
+    /// Capacity encrypt buffer is shrunk to after sending larger packet
+    pub encrypt_buffer_limit: Option<usize>,
+
+    /// Append and verify CRC-32 of plaintext
+    pub checksum: bool,
+}
+
+impl PacketLayer {
+    pub fn new(key: &Key<Aes128>) -> Self {
+        Self {
+            cipher: Aes128::new(key),
+
+            read_state: ReadState::Pending,
+
+            encrypt_buffer: Vec::new(),
+            encrypt_buffer_limit: Some(DEFAULT_ENCRYPT_BUFFER_LIMIT),
+            checksum: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.read_state = ReadState::Pending;
+        self.encrypt_buffer.clear();
+    }
+
+    pub fn read(&mut self, read_buffer: &mut VecDeque<u8>) -> Option<SecurePacket<Box<[u8]>>> {
+        loop {
+            match mem::replace(&mut self.read_state, ReadState::Corrupted) {
+                ReadState::Pending => {
+                    if read_buffer.len() < SECURE_HEAD_SIZE {
+                        self.read_state = ReadState::Pending;
+                        return None;
+                    }
+
+                    let raw_header = {
+                        let buf = read_buffer
+                            .drain(..SECURE_HEAD_SIZE)
+                            .collect::<ArrayVec<u8, SECURE_HEAD_SIZE>>();
+
+                        bincode_options().deserialize::<RawHeader>(&buf).unwrap()
+                    };
+
+                    // Size must include iv
+                    if (raw_header.size as usize) < SECURE_HEADER_SIZE {
+                        return None;
+                    }
+
+                    self.read_state = ReadState::Header(raw_header);
+                }
+
+                ReadState::Header(raw_header) => {
+                    let size = raw_header.size as usize - SECURE_HEADER_SIZE;
+
+                    if read_buffer.len() < size {
+                        self.read_state = ReadState::Header(raw_header);
+                        return None;
+                    }
+
+                    let mut data = read_buffer.drain(..size).collect::<Box<[u8]>>();
+                    Aes128CfbDec::inner_iv_init(self.cipher.clone(), &raw_header.iv.into())
+                        .decrypt(&mut data);
+
+                    if self.checksum {
+                        // Mismatching checksum leaves layer corrupted
+                        let data_size = data.len().checked_sub(checksum::CHECKSUM_SIZE)?;
+
+                        let (body, checksum) = data.split_at(data_size);
+                        if checksum != checksum::crc32(body).to_le_bytes() {
+                            return None;
+                        }
+
+                        data = Box::from(body);
+                    }
+
+                    self.read_state = ReadState::Pending;
+                    return Some(SecurePacket {
+                        iv: raw_header.iv,
+                        data,
+                    });
+                }
+
+                ReadState::Corrupted => return None,
+            }
+        }
+    }
+
+    pub fn has_packet(&self, read_buffer: &VecDeque<u8>) -> bool {
+        match self.read_state {
+            ReadState::Pending => {
+                if read_buffer.len() < SECURE_HEAD_SIZE {
+                    return false;
+                }
+
+                peek_size_prefix(read_buffer)
+                    .is_some_and(|size| read_buffer.len() - SECURE_SIZE_PREFIX >= size as usize)
+            }
+
+            ReadState::Header(ref raw_header) => {
+                read_buffer.len() >= raw_header.size as usize - SECURE_HEADER_SIZE
+            }
+
+            ReadState::Corrupted => false,
+        }
+    }
+
+    pub fn send(
+        &mut self,
+        write_buffer: &mut VecDeque<u8>,
+        packet: SecurePacket<impl AsRef<[u8]>>,
+    ) {
+        self.encrypt_buffer.clear();
+
+        let encrypted_data = {
+            let data = packet.data.as_ref();
+
+            self.encrypt_buffer.extend(data);
+
+            if self.checksum {
+                self.encrypt_buffer
+                    .extend(checksum::crc32(data).to_le_bytes());
+            }
+
+            Aes128CfbEnc::inner_iv_init(self.cipher.clone(), &packet.iv.into())
+                .encrypt(&mut self.encrypt_buffer);
+
+            &mut self.encrypt_buffer
+        };
+
+        bincode_options()
+            .serialize_into(
+                &mut *write_buffer,
+                &RawHeader {
+                    size: (SECURE_HEADER_SIZE + encrypted_data.len()) as u32,
+                    iv: packet.iv,
+                },
+            )
+            .unwrap();
+
+        write_buffer.extend(encrypted_data.drain(..));
+
+        if let Some(limit) = self.encrypt_buffer_limit {
+            if self.encrypt_buffer.capacity() > limit {
+                self.encrypt_buffer.shrink_to(limit);
+            }
+        }
+    }
+}
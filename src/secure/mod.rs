@@ -4,19 +4,260 @@
  * Copyright (c) storycraft. Licensed under the MIT Licence.
  */
 
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use arrayvec::ArrayVec;
+use bincode::Options;
+use core::{error::Error, fmt::Display};
+use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 
+use client::RawHeader;
+
+use crate::command::codec::bincode_options;
+
 pub mod client;
+pub mod crypto;
+pub mod server;
+
+mod checksum;
+mod layer;
+
+/// Default capacity encrypt buffer is shrunk to after sending larger packet
+pub const DEFAULT_ENCRYPT_BUFFER_LIMIT: usize = 64 * 1024;
+
+/// Size of secure packet header (iv), counted in packet size
+pub const SECURE_HEADER_SIZE: usize = 16;
+
+/// Size of u32 size prefix of secure packet and handshake packet
+pub const SECURE_SIZE_PREFIX: usize = 4;
+
+/// Size of packet size followed by secure packet header
+pub const SECURE_HEAD_SIZE: usize = SECURE_SIZE_PREFIX + SECURE_HEADER_SIZE;
+
+/// Size of handshake packet header (encrypted key size, key type, encrypt type)
+pub const HANDSHAKE_HEADER_SIZE: usize = 12;
+
+/// Maximum accepted size of handshake encrypted key
+///
+/// RSA-2048 encrypted key is 256 bytes
+pub const MAX_HANDSHAKE_KEY_SIZE: usize = 1024;
+
+/// Read u32 size prefix at start of `buf` without consuming it
+pub(crate) fn peek_size_prefix(buf: &VecDeque<u8>) -> Option<u32> {
+    if buf.len() < SECURE_SIZE_PREFIX {
+        return None;
+    }
+
+    Some(u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]))
+}
+
+/// Secure packet header with its encrypted data
+pub type EncryptedPacket<'a> = (RawHeader, &'a [u8]);
+
+/// Split `buf` into complete encrypted packets and trailing incomplete bytes
+///
+/// Each packet is returned with its encrypted data.
+/// Splitting stops at header with size smaller than [`SECURE_HEADER_SIZE`], which is returned as [`InvalidPacket`]
+/// instead of trailing bytes since it can never complete.
+pub fn split_packets(mut buf: &[u8]) -> (Vec<EncryptedPacket<'_>>, Result<&[u8], InvalidPacket>) {
+    let mut packets = Vec::new();
+    let mut offset = 0;
+
+    while buf.len() >= SECURE_HEAD_SIZE {
+        let raw_header = bincode_options()
+            .deserialize::<RawHeader>(&buf[..SECURE_HEAD_SIZE])
+            .unwrap();
+
+        let Some(size) = (raw_header.size as usize).checked_sub(SECURE_HEADER_SIZE) else {
+            return (
+                packets,
+                Err(InvalidPacket {
+                    offset,
+                    size: raw_header.size,
+                }),
+            );
+        };
+
+        if buf.len() - SECURE_HEAD_SIZE < size {
+            break;
+        }
+
+        let (data, rest) = buf[SECURE_HEAD_SIZE..].split_at(size);
+        packets.push((raw_header, data));
+        offset += SECURE_HEAD_SIZE + size;
+        buf = rest;
+    }
+
+    (packets, Ok(buf))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Secure packet header has size smaller than [`SECURE_HEADER_SIZE`]
+pub struct InvalidPacket {
+    /// Offset of invalid header in input
+    pub offset: usize,
+
+    /// Size in invalid header
+    pub size: u32,
+}
+
+impl Display for InvalidPacket {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "invalid secure packet size {} at offset {}",
+            self.size, self.offset
+        )
+    }
+}
+
+impl Error for InvalidPacket {}
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SecurePacket<T: ?Sized> {
+    /// AES-CFB iv
+    ///
+    /// Must be unique for every packet. Reusing iv with same key leaks plaintext.
     pub iv: [u8; 16],
     pub data: T,
 }
 
+impl<T> SecurePacket<T> {
+    /// Create new [`SecurePacket`] with iv generated from [`rand::thread_rng`]
+    #[cfg(feature = "std")]
+    pub fn with_random_iv(data: T) -> Self {
+        Self::with_iv_from_rng(data, &mut rand::thread_rng())
+    }
+
+    /// Create new [`SecurePacket`] with iv generated from `rng`
+    pub fn with_iv_from_rng<R: RngCore + CryptoRng>(data: T, rng: &mut R) -> Self {
+        let mut iv = [0_u8; 16];
+        rng.fill_bytes(&mut iv);
+
+        Self { iv, data }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Handshake packet containing encrypted key
+///
+/// ```
+/// use std::collections::VecDeque;
+///
+/// use loco_protocol::secure::{
+///     crypto::{EncryptType, KeyEncryptType},
+///     HandshakePacket, HANDSHAKE_HEADER_SIZE,
+/// };
+///
+/// let packet = HandshakePacket::new(
+///     KeyEncryptType::default().into(),
+///     EncryptType::default().into(),
+///     vec![1_u8, 2, 3].into_boxed_slice(),
+/// );
+///
+/// let mut buf = VecDeque::new();
+/// packet.encode(&mut buf);
+/// assert_eq!(buf.len(), HANDSHAKE_HEADER_SIZE + 3);
+///
+/// assert_eq!(HandshakePacket::decode(&mut buf), Some(packet));
+/// ```
 pub struct HandshakePacket<T: ?Sized> {
     key_type: u32,
     encrypt_type: u32,
     encrypted_key: T,
 }
+
+impl<T> HandshakePacket<T> {
+    /// Create new [`HandshakePacket`]
+    pub const fn new(key_type: u32, encrypt_type: u32, encrypted_key: T) -> Self {
+        Self {
+            key_type,
+            encrypt_type,
+            encrypted_key,
+        }
+    }
+
+    /// Encrypted key encryption type
+    pub const fn key_type(&self) -> u32 {
+        self.key_type
+    }
+
+    /// Data encryption type
+    pub const fn encrypt_type(&self) -> u32 {
+        self.encrypt_type
+    }
+
+    /// Encrypted key
+    pub const fn encrypted_key(&self) -> &T {
+        &self.encrypted_key
+    }
+
+    /// Convert into encrypted key
+    pub fn into_encrypted_key(self) -> T {
+        self.encrypted_key
+    }
+}
+
+impl<T: AsRef<[u8]>> HandshakePacket<T> {
+    /// Write handshake packet to `buf`
+    pub fn encode(&self, buf: &mut VecDeque<u8>) {
+        let encrypted_key = self.encrypted_key.as_ref();
+
+        bincode_options()
+            .serialize_into(
+                &mut *buf,
+                &RawHandshakeHeader {
+                    encrypted_key_size: encrypted_key.len() as u32,
+                    key_type: self.key_type,
+                    encrypt_type: self.encrypt_type,
+                },
+            )
+            .unwrap();
+
+        buf.extend(encrypted_key);
+    }
+}
+
+impl HandshakePacket<Box<[u8]>> {
+    /// Try to read handshake packet from `buf`
+    ///
+    /// Returns `None` and leaves `buf` untouched if it doesn't contain complete handshake packet
+    pub fn decode(buf: &mut VecDeque<u8>) -> Option<Self> {
+        if buf.len() < HANDSHAKE_HEADER_SIZE {
+            return None;
+        }
+
+        let raw_header = {
+            let header_buf = buf
+                .range(..HANDSHAKE_HEADER_SIZE)
+                .copied()
+                .collect::<ArrayVec<u8, HANDSHAKE_HEADER_SIZE>>();
+
+            bincode_options()
+                .deserialize::<RawHandshakeHeader>(&header_buf)
+                .unwrap()
+        };
+
+        if buf.len() - HANDSHAKE_HEADER_SIZE < raw_header.encrypted_key_size as usize {
+            return None;
+        }
+
+        buf.drain(..HANDSHAKE_HEADER_SIZE);
+        let encrypted_key = buf
+            .drain(..raw_header.encrypted_key_size as usize)
+            .collect::<Box<[u8]>>();
+
+        Some(Self {
+            key_type: raw_header.key_type,
+            encrypt_type: raw_header.encrypt_type,
+            encrypted_key,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RawHandshakeHeader {
+    encrypted_key_size: u32,
+    key_type: u32,
+    encrypt_type: u32,
+}
@@ -0,0 +1,287 @@
+/*
+ * Created on Thu Oct 15 2026
+ *
+ * Copyright (c) storycraft. Licensed under the MIT Licence.
+ */
+
+use alloc::{boxed::Box, collections::VecDeque};
+use core::{error::Error, fmt::Display};
+
+use aes::{cipher::Key, Aes128};
+use rand::{CryptoRng, RngCore};
+use rsa::RsaPrivateKey;
+
+use super::{
+    client::ReadState,
+    crypto::{CryptoError, EncryptType, KeyEncryptType},
+    layer::PacketLayer,
+    peek_size_prefix, HandshakePacket, SecurePacket, DEFAULT_ENCRYPT_BUFFER_LIMIT,
+    MAX_HANDSHAKE_KEY_SIZE,
+};
+
+#[derive(Debug)]
+/// IO-free server secure layer
+pub struct LocoServerSecureLayer {
+    layer: Option<PacketLayer>,
+
+    negotiated: Option<(KeyEncryptType, EncryptType)>,
+
+    handshake_failed: bool,
+
+    checksum: bool,
+
+    encrypt_buffer_limit: Option<usize>,
+
+    /// Read buffer for layer
+    pub read_buffer: VecDeque<u8>,
+
+    /// Write buffer for layer
+    pub write_buffer: VecDeque<u8>,
+}
+
+impl LocoServerSecureLayer {
+    /// Create new [`LocoServerSecureLayer`] waiting for handshake
+    pub const fn new() -> Self {
+        Self {
+            layer: None,
+
+            negotiated: None,
+
+            handshake_failed: false,
+
+            checksum: false,
+
+            encrypt_buffer_limit: Some(DEFAULT_ENCRYPT_BUFFER_LIMIT),
+
+            read_buffer: VecDeque::new(),
+            write_buffer: VecDeque::new(),
+        }
+    }
+
+    /// Returns `true` if handshake is completed
+    pub const fn is_handshaked(&self) -> bool {
+        self.layer.is_some()
+    }
+
+    /// Key encryption type sent by client
+    ///
+    /// Returns `None` if handshake is not completed
+    pub fn key_encrypt_type(&self) -> Option<KeyEncryptType> {
+        self.negotiated.map(|(key_type, _)| key_type)
+    }
+
+    /// Data encryption type sent by client
+    ///
+    /// Returns `None` if handshake is not completed
+    pub fn encrypt_type(&self) -> Option<EncryptType> {
+        self.negotiated.map(|(_, encrypt_type)| encrypt_type)
+    }
+
+    /// Returns `None` if handshake is not completed
+    pub fn read_state(&self) -> Option<&ReadState> {
+        self.layer.as_ref().map(|layer| &layer.read_state)
+    }
+
+    /// Set whether CRC-32 of plaintext is appended to sent packets and verified on read
+    ///
+    /// Mismatching checksum makes layer [`ReadState::Corrupted`].
+    /// Both ends must enable it and official clients don't support it. Disabled by default.
+    pub fn set_checksum(&mut self, checksum: bool) {
+        self.checksum = checksum;
+
+        if let Some(ref mut layer) = self.layer {
+            layer.checksum = checksum;
+        }
+    }
+
+    /// Set capacity internal encrypt buffer is shrunk to after sending larger packet
+    ///
+    /// `None` keeps allocated capacity. Defaults to [`DEFAULT_ENCRYPT_BUFFER_LIMIT`].
+    pub fn set_encrypt_buffer_limit(&mut self, limit: Option<usize>) {
+        self.encrypt_buffer_limit = limit;
+
+        if let Some(ref mut layer) = self.layer {
+            layer.encrypt_buffer_limit = limit;
+        }
+    }
+
+    /// Clear read and write buffer and discard handshake, waiting for new handshake
+    ///
+    /// Only call after tearing down underlying transport, as buffered data is discarded.
+    pub fn reset(&mut self) {
+        self.layer = None;
+        self.negotiated = None;
+        self.handshake_failed = false;
+
+        self.read_buffer.clear();
+        self.write_buffer.clear();
+    }
+
+    /// Try to read handshake packet from [`LocoServerSecureLayer::read_buffer`] and decrypt encrypt key using given private key
+    ///
+    /// Returns `None` if handshake packet is not fully buffered yet.
+    /// Returns `Some(Ok(()))` immediately if handshake is already completed.
+    /// Returns [`SecureHandshakeError::InvalidKey`] as soon as key size exceeding [`MAX_HANDSHAKE_KEY_SIZE`] is buffered.
+    ///
+    /// Once handshake fails, layer cannot continue and returns [`SecureHandshakeError::Failed`] afterwards.
+    #[cfg(feature = "std")]
+    pub fn read_handshake(
+        &mut self,
+        priv_key: &RsaPrivateKey,
+    ) -> Option<Result<(), SecureHandshakeError>> {
+        self.read_handshake_with_rng(priv_key, &mut rand::thread_rng())
+    }
+
+    /// Try to read handshake packet from [`LocoServerSecureLayer::read_buffer`] and decrypt encrypt key using given private key,
+    /// blinding decryption with `rng`
+    ///
+    /// Same as [`LocoServerSecureLayer::read_handshake`] except rng used for RSA blinding is supplied by caller.
+    pub fn read_handshake_with_rng<R: RngCore + CryptoRng>(
+        &mut self,
+        priv_key: &RsaPrivateKey,
+        rng: &mut R,
+    ) -> Option<Result<(), SecureHandshakeError>> {
+        if self.layer.is_some() {
+            return Some(Ok(()));
+        }
+
+        if self.handshake_failed {
+            return Some(Err(SecureHandshakeError::Failed));
+        }
+
+        // Reject oversized key before buffering it
+        if let Some(key_size) = peek_size_prefix(&self.read_buffer) {
+            if key_size as usize > MAX_HANDSHAKE_KEY_SIZE {
+                self.handshake_failed = true;
+                return Some(Err(SecureHandshakeError::InvalidKey));
+            }
+        }
+
+        let handshake = HandshakePacket::decode(&mut self.read_buffer)?;
+
+        let res = self.complete_handshake(priv_key, handshake, rng);
+        self.handshake_failed = res.is_err();
+        Some(res)
+    }
+
+    fn complete_handshake(
+        &mut self,
+        priv_key: &RsaPrivateKey,
+        handshake: HandshakePacket<Box<[u8]>>,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(), SecureHandshakeError> {
+        let key_type = KeyEncryptType::try_from(handshake.key_type())
+            .map_err(SecureHandshakeError::UnsupportedKeyType)?;
+
+        let encrypt_type = EncryptType::try_from(handshake.encrypt_type())
+            .map_err(SecureHandshakeError::UnsupportedEncryptType)?;
+
+        // Blind decryption of client controlled ciphertext against timing attacks
+        let key = priv_key
+            .decrypt_blinded(rng, key_type.padding(), handshake.encrypted_key())
+            .map_err(CryptoError::from)?;
+
+        let key: [u8; 16] = key
+            .as_slice()
+            .try_into()
+            .map_err(|_| SecureHandshakeError::InvalidKey)?;
+
+        let mut layer = PacketLayer::new(&Key::<Aes128>::from(key));
+        layer.checksum = self.checksum;
+        layer.encrypt_buffer_limit = self.encrypt_buffer_limit;
+
+        self.layer = Some(layer);
+        self.negotiated = Some((key_type, encrypt_type));
+        Ok(())
+    }
+
+    /// Try to read single [`SecurePacket`] from [`LocoServerSecureLayer::read_buffer`]
+    ///
+    /// Returns `None` if handshake is not completed.
+    /// Layer becomes [`ReadState::Corrupted`] on malformed packet and returns `None` afterwards
+    pub fn read(&mut self) -> Option<SecurePacket<Box<[u8]>>> {
+        self.layer.as_mut()?.read(&mut self.read_buffer)
+    }
+
+    /// Write single [`SecurePacket`] to [`LocoServerSecureLayer::write_buffer`]
+    pub fn send(
+        &mut self,
+        packet: SecurePacket<impl AsRef<[u8]>>,
+    ) -> Result<(), HandshakeRequired> {
+        self.layer
+            .as_mut()
+            .ok_or(HandshakeRequired)?
+            .send(&mut self.write_buffer, packet);
+
+        Ok(())
+    }
+}
+
+impl Default for LocoServerSecureLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+/// Server handshake error
+pub enum SecureHandshakeError {
+    /// Key encryption type is not supported
+    UnsupportedKeyType(u32),
+
+    /// Data encryption type is not supported
+    UnsupportedEncryptType(u32),
+
+    /// Encrypted key is too large or decrypted key is invalid
+    InvalidKey,
+
+    /// Key decryption failed
+    Crypto(CryptoError),
+
+    /// Handshake failed previously and layer cannot continue
+    Failed,
+}
+
+impl Display for SecureHandshakeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SecureHandshakeError::UnsupportedKeyType(ty) => {
+                write!(f, "unsupported key encrypt type: {ty}")
+            }
+            SecureHandshakeError::UnsupportedEncryptType(ty) => {
+                write!(f, "unsupported encrypt type: {ty}")
+            }
+            SecureHandshakeError::InvalidKey => write!(f, "invalid encrypt key"),
+            SecureHandshakeError::Crypto(err) => write!(f, "{err}"),
+            SecureHandshakeError::Failed => write!(f, "handshake failed previously"),
+        }
+    }
+}
+
+impl Error for SecureHandshakeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SecureHandshakeError::Crypto(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<CryptoError> for SecureHandshakeError {
+    fn from(err: CryptoError) -> Self {
+        Self::Crypto(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Layer cannot send packet before handshake is completed
+pub struct HandshakeRequired;
+
+impl Display for HandshakeRequired {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "handshake is not completed")
+    }
+}
+
+impl Error for HandshakeRequired {}
@@ -6,9 +6,13 @@
 
 use core::mem::swap;
 
+use serde::{Deserialize, Serialize};
+
 use loco_protocol::command::{
-    client::{LocoSink, LocoStream},
-    Command, Header, Method,
+    client::{
+        LocoSink, LocoStream, RequestTracker, StreamError, StreamState, DEFAULT_MAX_DATA_SIZE,
+    },
+    Command, DataType, Header, Method, Status,
 };
 
 #[test]
@@ -18,18 +22,477 @@ pub fn read_write_test() {
     let command = Command {
         header: Header {
             id: 0,
-            status: 1,
+            status: Status(1),
+            method: Method::new("TEST").unwrap(),
+            data_type: DataType(2),
+        },
+        data: Box::new([1_u8, 2, 3]) as Box<[u8]>,
+    };
+
+    sink.send(command.clone());
+
+    let mut stream = LocoStream::new();
+
+    swap(&mut stream.read_buffer, &mut sink.write_buffer);
+
+    assert_eq!(stream.read().unwrap(), Some(command));
+}
+
+#[test]
+pub fn drain_up_to_test() {
+    let mut sink = LocoSink::new();
+
+    let command = Command {
+        header: Header {
+            id: 0,
+            status: Status(0),
+            method: Method::new("TEST").unwrap(),
+            data_type: DataType(0),
+        },
+        data: Box::new([1_u8, 2, 3]) as Box<[u8]>,
+    };
+
+    for _ in 0..3 {
+        sink.send(command.clone());
+    }
+
+    let mut stream = LocoStream::new();
+    swap(&mut stream.read_buffer, &mut sink.write_buffer);
+
+    // Partial command must not count as remaining
+    stream.read_buffer.push_back(0);
+
+    assert_eq!(
+        stream.drain_up_to(2).unwrap(),
+        (vec![command.clone(), command.clone()], true)
+    );
+    assert_eq!(stream.drain_up_to(2).unwrap(), (vec![command], false));
+    assert_eq!(stream.read_buffer.len(), 1);
+}
+
+#[test]
+pub fn drain_up_to_error_test() {
+    let command = Command::new(0, 0, "TEST", 0, [1_u8, 2, 3]).unwrap();
+
+    let mut sink = LocoSink::new();
+    sink.send(command.clone());
+    sink.send(command.clone());
+
+    let mut stream = LocoStream::new();
+    swap(&mut stream.read_buffer, &mut sink.write_buffer);
+
+    // Invalid utf-8 method
+    stream.read_buffer.extend([0_u8; 6]);
+    stream.read_buffer.extend([0xff_u8; 11]);
+    stream.read_buffer.extend([0_u8; 5]);
+
+    assert_eq!(
+        stream.drain_up_to(10).unwrap(),
+        (vec![command.clone(), command], false)
+    );
+    assert_eq!(stream.state(), &StreamState::Corrupted);
+    assert!(matches!(
+        stream.drain_up_to(10),
+        Err(StreamError::Header(_))
+    ));
+    assert!(matches!(
+        stream.drain_up_to(10),
+        Err(StreamError::Corrupted)
+    ));
+
+    // Oversized frame after valid command
+    let mut stream = LocoStream::with_max_data_size(4);
+    sink.send(Command::new(1, 0, "TEST", 0, [1_u8, 2]).unwrap());
+    sink.send(Command::new(2, 0, "TEST", 0, [0_u8; 9]).unwrap());
+    swap(&mut stream.read_buffer, &mut sink.write_buffer);
+
+    assert_eq!(stream.drain_up_to(10).unwrap().0.len(), 1);
+    assert!(matches!(
+        stream.read(),
+        Err(StreamError::DataTooLarge { size: 9, max: 4 })
+    ));
+}
+
+#[test]
+pub fn read_error_test() {
+    let mut stream = LocoStream::new();
+
+    // Invalid utf-8 method
+    stream.read_buffer.extend([0_u8; 6]);
+    stream.read_buffer.extend([0xff_u8; 11]);
+    stream.read_buffer.extend([0_u8; 5]);
+
+    assert!(matches!(stream.read(), Err(StreamError::Header(_))));
+    assert_eq!(stream.state(), &StreamState::Corrupted);
+    assert!(matches!(stream.read(), Err(StreamError::Corrupted)));
+}
+
+#[test]
+pub fn read_data_too_large_test() {
+    let mut sink = LocoSink::new();
+
+    sink.send(Command {
+        header: Header {
+            id: 0,
+            status: Status(0),
+            method: Method::new("TEST").unwrap(),
+            data_type: DataType(0),
+        },
+        data: &[] as &[u8],
+    });
+
+    // Patch data_size to u32::MAX
+    for byte in sink.write_buffer.range_mut(18..) {
+        *byte = 0xff;
+    }
+
+    let mut stream = LocoStream::new();
+    swap(&mut stream.read_buffer, &mut sink.write_buffer);
+
+    assert!(matches!(
+        stream.read(),
+        Err(StreamError::DataTooLarge {
+            size: u32::MAX,
+            max: DEFAULT_MAX_DATA_SIZE
+        })
+    ));
+    assert_eq!(stream.state(), &StreamState::Corrupted);
+}
+
+#[test]
+pub fn max_data_size_test() {
+    let mut sink = LocoSink::new();
+
+    let command = Command {
+        header: Header {
+            id: 0,
+            status: Status(0),
+            method: Method::new("TEST").unwrap(),
+            data_type: DataType(0),
+        },
+        data: Box::new([0_u8; 8]) as Box<[u8]>,
+    };
+    sink.send(command.clone());
+    sink.send(command.clone());
+
+    let mut stream = LocoStream::with_max_data_size(8);
+    stream.read_buffer.extend(sink.write_buffer.drain(..30));
+    assert_eq!(stream.read().unwrap(), Some(command));
+
+    let mut stream = LocoStream::with_max_data_size(7);
+    stream.read_buffer.extend(sink.write_buffer.drain(..22));
+    assert!(matches!(
+        stream.read(),
+        Err(StreamError::DataTooLarge { size: 8, max: 7 })
+    ));
+}
+
+#[test]
+pub fn drain_test() {
+    let mut sink = LocoSink::new();
+
+    let command = Command {
+        header: Header {
+            id: 0,
+            status: Status(0),
+            method: Method::new("TEST").unwrap(),
+            data_type: DataType(0),
+        },
+        data: Box::new([1_u8, 2, 3]) as Box<[u8]>,
+    };
+    sink.send(command.clone());
+    sink.send(command.clone());
+
+    let mut stream = LocoStream::new();
+    stream.read_buffer.extend(sink.write_buffer.drain(..));
+
+    // Partial command
+    sink.send(command.clone());
+    stream.read_buffer.extend(sink.write_buffer.drain(..10));
+
+    let commands = stream.drain().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(commands, vec![command.clone(), command.clone()]);
+    assert_eq!(stream.read_buffer.len(), 10);
+
+    stream.read_buffer.extend(sink.write_buffer.drain(..));
+    assert_eq!(stream.drain().next().unwrap().unwrap(), command);
+}
+
+#[test]
+pub fn encode_header_test() {
+    let mut sink = LocoSink::new();
+
+    let command = Command {
+        header: Header {
+            id: 1,
+            status: Status(0),
+            method: Method::new("TEST").unwrap(),
+            data_type: DataType(0),
+        },
+        data: [1_u8, 2, 3],
+    };
+
+    let header = sink.encode_header(&command.header, 3);
+    sink.send(command);
+
+    assert_eq!(&header[..4], &[1, 0, 0, 0]);
+    assert_eq!(&header[18..], &[3, 0, 0, 0]);
+
+    assert_eq!(sink.write_buffer.len(), 22 + 3);
+    assert!(sink.write_buffer.iter().take(22).eq(header.iter()));
+}
+
+#[test]
+pub fn read_into_test() {
+    let mut sink = LocoSink::new();
+
+    let command = Command {
+        header: Header {
+            id: 0,
+            status: Status(0),
+            method: Method::new("TEST").unwrap(),
+            data_type: DataType(0),
+        },
+        data: vec![1_u8, 2, 3],
+    };
+    sink.send(command.clone());
+
+    let mut stream = LocoStream::new();
+    stream.read_buffer.extend(sink.write_buffer.drain(..24));
+
+    let mut data = vec![4_u8; 8];
+    assert_eq!(stream.read_into(&mut data).unwrap(), None);
+    assert_eq!(data, vec![4_u8; 8]);
+
+    stream.read_buffer.extend(sink.write_buffer.drain(..));
+    assert_eq!(
+        stream.read_into(&mut data).unwrap(),
+        Some(command.header.clone())
+    );
+    assert_eq!(data, command.data);
+}
+
+#[test]
+pub fn read_header_test() {
+    let mut sink = LocoSink::new();
+
+    let command = Command {
+        header: Header {
+            id: 1,
+            status: Status(0),
             method: Method::new("TEST").unwrap(),
-            data_type: 2,
+            data_type: DataType(0),
         },
         data: Box::new([1_u8, 2, 3]) as Box<[u8]>,
     };
+    sink.send(command.clone());
+
+    let mut stream = LocoStream::new();
+    stream.read_buffer.extend(sink.write_buffer.drain(..23));
+
+    let raw_header = stream.read_header().unwrap().unwrap();
+    assert_eq!(raw_header.header, command.header);
+    assert_eq!(raw_header.data_size, 3);
+
+    // Header is kept until data is read
+    assert_eq!(stream.read().unwrap(), None);
+    assert_eq!(
+        stream.read_header().unwrap().map(|raw| raw.header.id),
+        Some(1)
+    );
+
+    stream.read_buffer.extend(sink.write_buffer.drain(..));
+    assert_eq!(stream.read().unwrap(), Some(command));
+    assert_eq!(stream.read_header().unwrap(), None);
+}
+
+#[test]
+pub fn read_tracked_test() {
+    let mut sink = LocoSink::new();
+    let mut tracker = RequestTracker::new();
+
+    let request = Command::new(1, 0, "TEST", 0, []).unwrap();
+    tracker.register(request.header.id);
+
+    let broadcast = Command::new(2, 0, "TEST", 0, []).unwrap();
+
+    sink.send(broadcast.clone());
+    sink.send(request.clone());
+    sink.send(request.clone());
+
+    let mut stream = LocoStream::new();
+    swap(&mut stream.read_buffer, &mut sink.write_buffer);
+
+    assert_eq!(
+        stream.read_tracked(&mut tracker).unwrap(),
+        Some((false, broadcast))
+    );
+    assert_eq!(
+        stream.read_tracked(&mut tracker).unwrap(),
+        Some((true, request.clone()))
+    );
+    assert!(tracker.is_empty());
+
+    // Response is matched only once
+    assert_eq!(
+        stream.read_tracked(&mut tracker).unwrap(),
+        Some((false, request))
+    );
+    assert_eq!(stream.read_tracked(&mut tracker).unwrap(), None);
+}
+
+#[test]
+pub fn typed_body_test() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Body {
+        id: u64,
+        name: String,
+    }
+
+    let body = Body {
+        id: 1,
+        name: "loco".into(),
+    };
+
+    let mut sink = LocoSink::new();
+    sink.send_typed(
+        Header {
+            id: 0,
+            status: Status(0),
+            method: Method::new("TEST").unwrap(),
+            data_type: DataType(0),
+        },
+        &body,
+    )
+    .unwrap();
 
+    let mut stream = LocoStream::new();
+    swap(&mut stream.read_buffer, &mut sink.write_buffer);
+
+    let command = stream.read().unwrap().unwrap();
+    assert_eq!(command.parse_body::<Body>().unwrap(), body);
+}
+
+#[cfg(feature = "std")]
+#[test]
+pub fn flush_to_test() {
+    use std::io::{self, Write};
+
+    // Accepts at most 5 bytes per write
+    struct PartialWriter(Vec<u8>);
+
+    impl Write for PartialWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = buf.len().min(5);
+            self.0.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let command = Command::new(0, 0, "TEST", 0, [1_u8, 2, 3]).unwrap();
+
+    let mut sink = LocoSink::new();
+    sink.send(command.clone());
+
+    let mut writer = PartialWriter(Vec::new());
+    let mut written = 0;
+    while !sink.write_buffer.is_empty() {
+        let size = sink.flush_to(&mut writer).unwrap();
+        assert!(size <= 5);
+        written += size;
+    }
+    assert_eq!(written, 25);
+
+    let mut stream = LocoStream::new();
+    stream.read_buffer.extend(writer.0);
+    assert_eq!(stream.read().unwrap(), Some(command));
+}
+
+#[test]
+pub fn feed_slice_test() {
+    let command = Command::new(0, 0, "TEST", 0, [1_u8, 2, 3]).unwrap();
+
+    let mut sink = LocoSink::new();
+    sink.send(command.clone());
+    let buf = sink.write_buffer.make_contiguous();
+
+    let mut stream = LocoStream::new();
+
+    stream.feed_slice(&buf[..10]);
+    assert_eq!(stream.read().unwrap(), None);
+
+    stream.feed_slice(&buf[10..]);
+    assert_eq!(stream.read().unwrap(), Some(command));
+}
+
+#[cfg(feature = "std")]
+#[test]
+pub fn feed_test() {
+    use std::io::Cursor;
+
+    let command = Command::new(0, 0, "TEST", 0, [1_u8; 8]).unwrap();
+
+    let mut sink = LocoSink::new();
+    sink.send(command.clone());
     sink.send(command.clone());
 
+    let mut reader = Cursor::new(sink.write_buffer.iter().copied().collect::<Vec<_>>());
+
+    // Buffer can hold single command at most
+    let mut stream = LocoStream::with_max_data_size(8);
+    assert_eq!(stream.feed(&mut reader).unwrap(), 30);
+    assert_eq!(stream.feed(&mut reader).unwrap(), 0);
+
+    assert_eq!(stream.read().unwrap(), Some(command.clone()));
+    assert_eq!(stream.feed(&mut reader).unwrap(), 30);
+    assert_eq!(stream.read().unwrap(), Some(command));
+}
+
+#[test]
+pub fn send_borrowed_test() {
+    let mut sink = LocoSink::new();
+
+    let header = Header {
+        id: 1,
+        status: Status(0),
+        method: Method::new("TEST").unwrap(),
+        data_type: DataType(0),
+    };
+    let data = [1_u8, 2, 3, 4];
+
+    sink.send(Command::borrowed(header.clone(), &data));
+
     let mut stream = LocoStream::new();
+    swap(&mut stream.read_buffer, &mut sink.write_buffer);
 
+    let command = stream.read().unwrap().unwrap();
+    assert_eq!(command.header, header);
+    assert_eq!(&*command.data, &data);
+}
+
+#[test]
+pub fn reset_test() {
+    let mut stream = LocoStream::new();
+
+    stream.read_buffer.extend([0_u8; 6]);
+    stream.read_buffer.extend([0xff_u8; 11]);
+    stream.read_buffer.extend([0_u8; 5]);
+
+    assert!(stream.read().is_err());
+    assert_eq!(stream.state(), &StreamState::Corrupted);
+
+    stream.reset();
+    assert_eq!(stream.state(), &StreamState::Pending);
+    assert!(stream.read_buffer.is_empty());
+
+    let command = Command::new(1, 0, "TEST", 0, [1_u8, 2, 3]).unwrap();
+    let mut sink = LocoSink::new();
+    sink.send(command.clone());
     swap(&mut stream.read_buffer, &mut sink.write_buffer);
 
-    assert_eq!(stream.read(), Some(command));
+    assert_eq!(stream.read().unwrap(), Some(command));
 }
@@ -0,0 +1,410 @@
+/*
+ * Created on Thu Oct 15 2026
+ *
+ * Copyright (c) storycraft. Licensed under the MIT Licence.
+ */
+
+use std::{collections::HashSet, sync::Arc, thread};
+
+use bincode::Options;
+
+use loco_protocol::command::{
+    client::{LocoSink, RawHeader, StreamError, DEFAULT_MAX_DATA_SIZE},
+    codec::{bincode_options, decode_command, decode_command_with_max},
+    known::KnownMethod,
+    parse_all, ping_request, pong_response, Command, CommandError, DataType, FrameError, Header,
+    IdAllocator, Method, MethodTooLong, ResendQueue, Router, Status, HEADER_SIZE, HEAD_SIZE,
+};
+
+#[test]
+pub fn command_new_test() {
+    let command = Command::new(0, 1, "TEST", 2, [1_u8, 2, 3]).unwrap();
+
+    assert_eq!(
+        command,
+        Command {
+            header: Header {
+                id: 0,
+                status: Status(1),
+                method: Method::new("TEST").unwrap(),
+                data_type: DataType(2),
+            },
+            data: Box::new([1_u8, 2, 3]) as Box<[u8]>,
+        }
+    );
+
+    assert_eq!(
+        Command::new(0, 0, "TOOLONGMETHOD", 0, []),
+        Err(MethodTooLong)
+    );
+}
+
+#[test]
+pub fn method_bytes_test() {
+    let method = Method::from_bytes(*b"TEST\0\0\0\0\0\0\0");
+
+    assert_eq!(method.len(), 4);
+    assert_eq!(method.as_str(), Ok("TEST"));
+    assert_eq!(method, Method::new("TEST").unwrap());
+    assert_eq!(method.as_bytes(), b"TEST\0\0\0\0\0\0\0");
+
+    let method = Method::from_bytes([0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    assert_eq!(method.len(), 1);
+    assert!(method.as_str().is_err());
+}
+
+#[test]
+pub fn method_malformed_deserialize_test() {
+    // Leading `\0` followed by multi byte character
+    let method = bincode::deserialize::<Method>(&[0, 0xc3, 0xa9, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+    assert_eq!(&*method, "\0é");
+
+    assert!(bincode::deserialize::<Method>(&[0xc3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+
+    let method = Method::from_bytes([b'A', 0xc3, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(&*method, "A");
+}
+
+#[test]
+pub fn method_invalid_debug_test() {
+    let method = Method::from_bytes(*b"PING\xff\0\0\0\0\0\0");
+
+    // Deref is lossy, checked comparisons are not
+    assert_eq!(&*method, "PING");
+    assert_ne!(method, "PING");
+    assert_eq!(method.to_known(), None);
+
+    assert_eq!(format!("{method:?}"), "Method([80, 73, 78, 71, 255])");
+    assert_eq!(
+        format!("{:?}", Method::new("PING").unwrap()),
+        "Method(\"PING\")"
+    );
+}
+
+#[test]
+pub fn method_str_eq_test() {
+    let method = Method::new("GETTRAILER").unwrap();
+
+    assert!(method == "GETTRAILER");
+    assert!(*"GETTRAILER" == method);
+    assert!(method != "GETTRAILE");
+    assert!(method != "GETTRAILERS");
+
+    let method = Method::new("ABCDEFGHIJK").unwrap();
+    assert!(method == "ABCDEFGHIJK");
+    assert!(method != "ABCDEFGHIJ");
+
+    const fn is_ping(method: &Method) -> bool {
+        method.eq_str("PING")
+    }
+    assert!(is_ping(&Method::new("PING").unwrap()));
+    assert!(!is_ping(&Method::new("PIN").unwrap()));
+}
+
+#[test]
+pub fn known_method_test() {
+    for &known in KnownMethod::ALL {
+        let method = known.as_method();
+
+        assert_eq!(&*method, known.as_str());
+        assert_eq!(method.to_known(), Some(known));
+    }
+
+    assert_eq!(
+        Method::new("GETTRAILER").unwrap().to_known(),
+        Some(KnownMethod::GetTrailer)
+    );
+    assert_eq!(Method::new("UNKNOWN").unwrap().to_known(), None);
+}
+
+#[test]
+pub fn validate_request_test() {
+    let command = Command::new(0, 0, "TEST", 0, []).unwrap();
+    assert_eq!(command.validate_request(), Ok(()));
+
+    let command = Command::new(0, 0, "", 0, []).unwrap();
+    assert_eq!(command.validate_request(), Err(CommandError::EmptyMethod));
+}
+
+#[test]
+pub fn id_allocator_test() {
+    let allocator = Arc::new(IdAllocator::default());
+
+    let handles = (0..2)
+        .map(|_| {
+            let allocator = allocator.clone();
+            thread::spawn(move || (0..1000).map(|_| allocator.next()).collect::<Vec<_>>())
+        })
+        .collect::<Vec<_>>();
+
+    let mut ids = HashSet::new();
+    for handle in handles {
+        for id in handle.join().unwrap() {
+            assert!(ids.insert(id));
+        }
+    }
+    assert_eq!(ids.len(), 2000);
+
+    let allocator = IdAllocator::new(u32::MAX).skip_zero(true);
+    assert_eq!(allocator.next(), u32::MAX);
+    assert_eq!(allocator.next(), 1);
+
+    let allocator = IdAllocator::new(u32::MAX);
+    assert_eq!(allocator.next(), u32::MAX);
+    assert_eq!(allocator.next(), 0);
+}
+
+#[test]
+pub fn resend_queue_test() {
+    let mut queue = ResendQueue::new();
+
+    let first = Command::new(1, 0, "TEST", 0, [1_u8]).unwrap();
+    let second = Command::new(2, 0, "TEST", 0, [2_u8]).unwrap();
+
+    queue.push(first.clone());
+    queue.push(second.clone());
+
+    // Response of first command arrived, second response was lost
+    assert_eq!(queue.ack(1), Some(first));
+    assert_eq!(queue.ack(3), None);
+
+    // Resend after reconnect
+    let resend = queue.pending().cloned().collect::<Vec<_>>();
+    assert_eq!(resend, vec![second.clone()]);
+
+    assert_eq!(queue.ack(2), Some(second));
+    assert!(queue.is_empty());
+}
+
+#[test]
+pub fn error_test() {
+    let errors: Vec<Box<dyn std::error::Error>> = vec![
+        Box::new(MethodTooLong),
+        Box::new(CommandError::EmptyMethod),
+        Box::new(StreamError::Corrupted),
+        Box::new(StreamError::Header(
+            bincode::deserialize::<u32>(&[]).unwrap_err(),
+        )),
+    ];
+
+    for err in &errors {
+        assert!(!err.to_string().is_empty());
+    }
+    assert!(errors[3].source().is_some());
+}
+
+#[test]
+pub fn parse_all_test() {
+    let (commands, rest) = parse_all(&[]);
+    assert!(commands.is_empty());
+    assert_eq!(rest.unwrap(), 0);
+
+    let (commands, rest) = parse_all(&[0_u8; 10]);
+    assert!(commands.is_empty());
+    assert_eq!(rest.unwrap(), 10);
+
+    let first = Command::new(0, 0, "TEST", 0, [1_u8, 2, 3]).unwrap();
+    let second = Command::new(1, 0, "TEST", 0, []).unwrap();
+
+    let mut sink = LocoSink::new();
+    sink.send(first.clone());
+    sink.send(second.clone());
+    sink.send(first.clone());
+
+    // Dangling partial header
+    let buf = &sink.write_buffer.make_contiguous()[..25 + 22 + 10];
+
+    let (commands, rest) = parse_all(buf);
+    assert_eq!(commands, vec![first, second]);
+    assert_eq!(rest.unwrap(), 10);
+}
+
+#[test]
+pub fn parse_all_corrupt_test() {
+    let command = Command::new(0, 0, "TEST", 0, [1_u8, 2, 3]).unwrap();
+
+    let mut sink = LocoSink::new();
+    sink.send(command.clone());
+    let offset = sink.write_buffer.len();
+
+    // Invalid utf-8 method in the middle of capture
+    sink.write_buffer.extend([0_u8; 6]);
+    sink.write_buffer.extend([0xff_u8; 11]);
+    sink.write_buffer.extend([0_u8; 5]);
+    sink.send(command.clone());
+
+    let (commands, rest) = parse_all(sink.write_buffer.make_contiguous());
+    assert_eq!(commands, vec![command]);
+    assert!(matches!(
+        rest,
+        Err(FrameError {
+            offset: err_offset,
+            error: StreamError::Header(_),
+        }) if err_offset == offset
+    ));
+}
+
+#[test]
+pub fn header_newtype_test() {
+    let header = Header {
+        id: 1,
+        status: Status(-300),
+        method: Method::new("TEST").unwrap(),
+        data_type: DataType::BSON,
+    };
+
+    assert!(header.status.is_error());
+    assert!(!header.status.is_success());
+    assert!(Status::SUCCESS.is_success());
+
+    // Wire bytes are identical to raw integers
+    let bytes = bincode::serialize(&header).unwrap();
+    assert_eq!(bytes.len(), 18);
+    assert_eq!(&bytes[4..6], &(-300_i16).to_le_bytes());
+    assert_eq!(bytes[17], 0);
+
+    let raw = bincode::serialize(&(1_u32, -300_i16, &header.method, 0_u8)).unwrap();
+    assert_eq!(bytes, raw);
+
+    assert_eq!(bincode::deserialize::<Header>(&bytes).unwrap(), header);
+}
+
+#[test]
+pub fn bincode_options_test() {
+    let header = Header {
+        id: 0x01020304,
+        status: Status(-2),
+        method: Method::new("TEST").unwrap(),
+        data_type: DataType(5),
+    };
+
+    assert_eq!(
+        bincode_options().serialize(&header).unwrap(),
+        [0x04, 0x03, 0x02, 0x01, 0xfe, 0xff, b'T', b'E', b'S', b'T', 0, 0, 0, 0, 0, 0, 0, 5]
+    );
+}
+
+#[test]
+pub fn header_layout_test() {
+    let header = Header {
+        id: 0xffeeddcc,
+        status: Status(i16::MIN),
+        method: Method::new("LOGINLIST12").unwrap(),
+        data_type: DataType(0xff),
+    };
+
+    let header_bytes = bincode_options().serialize(&header).unwrap();
+    assert_eq!(header_bytes.len(), HEADER_SIZE);
+    assert_eq!(
+        header_bytes,
+        [
+            0xcc, 0xdd, 0xee, 0xff, 0x00, 0x80, b'L', b'O', b'G', b'I', b'N', b'L', b'I', b'S',
+            b'T', b'1', b'2', 0xff
+        ]
+    );
+
+    let raw_header_bytes = bincode_options()
+        .serialize(&RawHeader {
+            header,
+            data_size: 0x04030201,
+        })
+        .unwrap();
+    assert_eq!(raw_header_bytes.len(), HEAD_SIZE);
+    assert_eq!(raw_header_bytes[..HEADER_SIZE], header_bytes[..]);
+    assert_eq!(raw_header_bytes[HEADER_SIZE..], [0x01, 0x02, 0x03, 0x04]);
+}
+
+#[test]
+pub fn ping_test() {
+    let ping = Command::new(5, 0, "PING", 0, []).unwrap();
+    assert!(ping.is_ping());
+    assert!(!Command::new(5, 0, "PINGX", 0, []).unwrap().is_ping());
+    assert!(!Command::new(5, 0, "LOGINLIST", 0, []).unwrap().is_ping());
+
+    let pong = pong_response(&ping);
+    assert!(pong.is_ping());
+    assert_eq!(pong.header.id, ping.header.id);
+    assert_eq!(pong.header.status, Status::SUCCESS);
+    assert_eq!(pong.header.data_type, ping.header.data_type);
+    assert!(pong.data.is_empty());
+
+    let id_alloc = IdAllocator::new(10);
+    let request = ping_request(&id_alloc);
+    assert!(request.is_ping());
+    assert_eq!(request.header.id, 10);
+    assert_eq!(ping_request(&id_alloc).header.id, 11);
+}
+
+#[test]
+pub fn router_test() {
+    let mut router = Router::new();
+
+    router
+        .on("MSG", |command| ("MSG", command.header.id))
+        .unwrap();
+    router
+        .on("PING", |command| ("PING", command.header.id))
+        .unwrap();
+    assert_eq!(router.on("TOOLONGMETHOD", |_| ("", 0)), Err(MethodTooLong));
+
+    let command = |id, method| Command::new(id, 0, method, 0, []).unwrap();
+
+    assert_eq!(router.dispatch(command(1, "MSG")), Some(("MSG", 1)));
+    assert_eq!(router.dispatch(command(2, "PING")), Some(("PING", 2)));
+    assert_eq!(router.dispatch(command(3, "WRITE")), None);
+
+    router.fallback(|command| ("fallback", command.header.id));
+    assert_eq!(router.dispatch(command(4, "WRITE")), Some(("fallback", 4)));
+}
+
+#[test]
+pub fn decode_command_test() {
+    let command = Command::new(3, 0, "TEST", 0, [1_u8, 2, 3]).unwrap();
+
+    let mut sink = LocoSink::new();
+    sink.send(command.clone());
+    sink.write_buffer.push_back(0xff);
+    let buf = sink.write_buffer.make_contiguous();
+
+    // Complete frame followed by extra byte
+    assert_eq!(decode_command(buf).unwrap(), Some((command, HEAD_SIZE + 3)));
+
+    // Header only
+    assert_eq!(decode_command(&buf[..HEAD_SIZE]).unwrap(), None);
+    assert_eq!(decode_command(&buf[..HEAD_SIZE - 1]).unwrap(), None);
+
+    // Oversized data size
+    buf[HEADER_SIZE..HEAD_SIZE].copy_from_slice(&(DEFAULT_MAX_DATA_SIZE + 1).to_le_bytes());
+    assert!(matches!(
+        decode_command(&buf[..HEAD_SIZE]),
+        Err(StreamError::DataTooLarge { size, max: DEFAULT_MAX_DATA_SIZE }) if size == DEFAULT_MAX_DATA_SIZE + 1
+    ));
+    let (commands, rest) = parse_all(buf);
+    assert!(commands.is_empty());
+    assert!(matches!(
+        rest,
+        Err(FrameError {
+            offset: 0,
+            error: StreamError::DataTooLarge { .. },
+        })
+    ));
+}
+
+#[test]
+pub fn decode_command_with_max_test() {
+    let command = Command::new(3, 0, "TEST", 0, [1_u8, 2, 3]).unwrap();
+
+    let mut sink = LocoSink::new();
+    sink.send(command.clone());
+    let buf = sink.write_buffer.make_contiguous();
+
+    assert_eq!(
+        decode_command_with_max(buf, 3).unwrap(),
+        Some((command, HEAD_SIZE + 3))
+    );
+    assert!(matches!(
+        decode_command_with_max(buf, 2),
+        Err(StreamError::DataTooLarge { size: 3, max: 2 })
+    ));
+}
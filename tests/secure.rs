@@ -5,11 +5,24 @@
  */
 
 use core::mem::swap;
+use std::collections::VecDeque;
 
-use loco_protocol::secure::{client::LocoClientSecureLayer, SecurePacket};
-use rand::RngCore;
+use loco_protocol::{
+    command::{
+        client::{LocoSink, LocoStream},
+        Command, DataType, Header, Method, Status,
+    },
+    secure::{
+        client::{LocoClientSecureLayer, ReadState},
+        crypto::{constant_time_eq, EncryptType, KeyEncryptType},
+        server::LocoServerSecureLayer,
+        split_packets, HandshakePacket, InvalidPacket, SecurePacket,
+    },
+};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
 use rsa::{RsaPrivateKey, RsaPublicKey};
 
+#[cfg(feature = "std")]
 #[test]
 pub fn test_handshake() {
     let mut layer = LocoClientSecureLayer::new([0_u8; 16]);
@@ -18,9 +31,34 @@ pub fn test_handshake() {
         RsaPrivateKey::new(&mut rand::thread_rng(), 2048).expect("failed to generate a key");
     let pub_key = RsaPublicKey::from(&priv_key);
 
-    layer.handshake(&pub_key);
+    layer.write_buffer.extend([0_u8; 4]);
 
-    assert_eq!(layer.write_buffer.len(), 12 + 256);
+    assert_eq!(layer.handshake(&pub_key).unwrap(), 12 + 256);
+    assert_eq!(layer.write_buffer.len(), 4 + 12 + 256);
+
+    layer.write_buffer.drain(..4);
+    let handshake = HandshakePacket::decode(&mut layer.write_buffer).unwrap();
+
+    assert_eq!(handshake.key_type(), 15);
+    assert_eq!(handshake.encrypt_type(), 2);
+    assert_eq!(handshake.encrypted_key().len(), 256);
+    assert!(layer.write_buffer.is_empty());
+}
+
+#[test]
+pub fn handshake_packet_test() {
+    let packet = HandshakePacket::new(15, 2, Box::new([1_u8, 2, 3]) as Box<[u8]>);
+
+    let mut buf = VecDeque::new();
+    packet.encode(&mut buf);
+    assert_eq!(buf.len(), 12 + 3);
+
+    let last = buf.pop_back().unwrap();
+    assert_eq!(HandshakePacket::decode(&mut buf), None);
+    assert_eq!(buf.len(), 12 + 2);
+
+    buf.push_back(last);
+    assert_eq!(HandshakePacket::decode(&mut buf), Some(packet));
 }
 
 #[test]
@@ -45,3 +83,596 @@ pub fn read_write_test() {
 
     assert_eq!(layer.read(), Some(packet));
 }
+
+#[test]
+pub fn command_over_secure_layer_test() {
+    let mut layer = LocoClientSecureLayer::new([1_u8; 16]);
+
+    let command = Command {
+        header: Header {
+            id: 0,
+            status: Status(0),
+            method: Method::new("TEST").unwrap(),
+            data_type: DataType(0),
+        },
+        data: Box::new([1_u8, 2, 3, 4, 5]) as Box<[u8]>,
+    };
+
+    let mut sink = LocoSink::new();
+    sink.send(command.clone());
+    sink.send(command.clone());
+
+    // Split commands across secure packets so the second packet holds a partial command
+    let (first, second) = sink.write_buffer.make_contiguous().split_at(30);
+    for data in [first, second] {
+        layer.send(SecurePacket {
+            iv: [2_u8; 16],
+            data,
+        });
+    }
+
+    swap(&mut layer.read_buffer, &mut layer.write_buffer);
+
+    let mut stream = LocoStream::new();
+
+    stream.read_buffer.extend(layer.read().unwrap().data.iter());
+    assert_eq!(stream.read().unwrap(), Some(command.clone()));
+    assert_eq!(stream.read().unwrap(), None);
+
+    stream.read_buffer.extend(layer.read().unwrap().data.iter());
+    assert_eq!(stream.read().unwrap(), Some(command));
+    assert_eq!(stream.read().unwrap(), None);
+}
+
+#[test]
+pub fn drain_up_to_test() {
+    let mut layer = LocoClientSecureLayer::new([0_u8; 16]);
+
+    let packet = SecurePacket {
+        iv: [0_u8; 16],
+        data: Box::new([0_u8, 1, 2]) as Box<[u8]>,
+    };
+
+    for _ in 0..3 {
+        layer.send(packet.clone());
+    }
+
+    swap(&mut layer.read_buffer, &mut layer.write_buffer);
+
+    assert_eq!(
+        layer.drain_up_to(2),
+        (vec![packet.clone(), packet.clone()], true)
+    );
+    assert_eq!(layer.drain_up_to(2), (vec![packet], false));
+}
+
+#[cfg(feature = "std")]
+#[test]
+pub fn pump_test() {
+    use std::io::{self, Cursor, Read, Write};
+
+    struct Loopback {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Loopback {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Loopback {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let packet = SecurePacket {
+        iv: [0_u8; 16],
+        data: Box::new([0_u8, 1, 2]) as Box<[u8]>,
+    };
+
+    let mut layer = LocoClientSecureLayer::new([0_u8; 16]);
+    layer.send(packet.clone());
+
+    let mut io = Loopback {
+        input: Cursor::new(layer.write_buffer.iter().copied().collect()),
+        output: Vec::new(),
+    };
+
+    assert_eq!(layer.pump(&mut io).unwrap(), 23);
+    assert!(layer.write_buffer.is_empty());
+    assert_eq!(io.output.len(), 23);
+    assert_eq!(layer.read(), Some(packet));
+
+    assert_eq!(layer.pump(&mut io).unwrap(), 0);
+}
+
+#[test]
+pub fn split_packets_test() {
+    let mut layer = LocoClientSecureLayer::new([0_u8; 16]);
+
+    for data in [&[0_u8, 1, 2][..], &[3, 4]] {
+        layer.send(SecurePacket {
+            iv: [1_u8; 16],
+            data,
+        });
+    }
+    let buf = layer.write_buffer.make_contiguous();
+
+    let (packets, rest) = split_packets(buf);
+    assert_eq!(packets.len(), 2);
+    assert_eq!(packets[0].0.size as usize, 16 + 3);
+    assert_eq!(packets[0].0.iv, [1_u8; 16]);
+    assert_eq!(packets[0].1.len(), 3);
+    assert_eq!(packets[1].1.len(), 2);
+    assert_eq!(rest, Ok(&[][..]));
+
+    // No complete packet
+    let (packets, rest) = split_packets(&buf[..22]);
+    assert!(packets.is_empty());
+    assert_eq!(rest.unwrap().len(), 22);
+
+    let (packets, rest) = split_packets(&buf[..30]);
+    assert_eq!(packets.len(), 1);
+    assert_eq!(rest, Ok(&buf[23..30]));
+}
+
+#[test]
+pub fn split_packets_invalid_test() {
+    let mut layer = LocoClientSecureLayer::new([0_u8; 16]);
+    layer.send(SecurePacket {
+        iv: [1_u8; 16],
+        data: &[0_u8, 1, 2][..],
+    });
+    let valid_len = layer.write_buffer.len();
+
+    // Size smaller than iv
+    layer.write_buffer.extend(4_u32.to_le_bytes());
+    layer.write_buffer.extend([0_u8; 16 + 4]);
+    let buf = layer.write_buffer.make_contiguous();
+
+    let (packets, rest) = split_packets(buf);
+    assert_eq!(packets.len(), 1);
+    assert_eq!(
+        rest,
+        Err(InvalidPacket {
+            offset: valid_len,
+            size: 4
+        })
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+pub fn handshake_small_key_test() {
+    use loco_protocol::secure::crypto::CryptoError;
+
+    let mut layer = LocoClientSecureLayer::new([0_u8; 16]);
+
+    // Too small for OAEP SHA1 padding of 16 bytes key
+    let priv_key =
+        RsaPrivateKey::new(&mut rand::thread_rng(), 256).expect("failed to generate a key");
+    let pub_key = RsaPublicKey::from(&priv_key);
+
+    let err = layer.handshake(&pub_key).unwrap_err();
+    assert!(matches!(err, CryptoError::Rsa(_)));
+
+    let err: Box<dyn std::error::Error> = Box::new(err);
+    assert!(err.source().is_some());
+    assert!(layer.write_buffer.is_empty());
+}
+
+#[test]
+pub fn restore_key_test() {
+    let mut layer = LocoClientSecureLayer::new([3_u8; 16]);
+
+    let packet = SecurePacket {
+        iv: [0_u8; 16],
+        data: Box::new([0_u8, 1, 2]) as Box<[u8]>,
+    };
+    layer.send(packet.clone());
+
+    let mut restored = LocoClientSecureLayer::new(*layer.key());
+    swap(&mut restored.read_buffer, &mut layer.write_buffer);
+
+    assert_eq!(restored.read(), Some(packet));
+}
+
+#[test]
+pub fn constant_time_eq_test() {
+    assert!(constant_time_eq(b"token", b"token"));
+    assert!(constant_time_eq(b"", b""));
+    assert!(!constant_time_eq(b"token", b"tokem"));
+    assert!(!constant_time_eq(b"token", b"token!"));
+}
+
+#[test]
+pub fn read_invalid_size_test() {
+    let mut layer = LocoClientSecureLayer::new([0_u8; 16]);
+
+    layer.read_buffer.extend(4_u32.to_le_bytes());
+    layer.read_buffer.extend([0_u8; 16 + 4]);
+
+    assert_eq!(layer.read(), None);
+    assert_eq!(layer.read_state(), &ReadState::Corrupted);
+    assert_eq!(layer.read(), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+pub fn client_server_test() {
+    let priv_key =
+        RsaPrivateKey::new(&mut rand::thread_rng(), 2048).expect("failed to generate a key");
+    let pub_key = RsaPublicKey::from(&priv_key);
+
+    let mut client = LocoClientSecureLayer::new([7_u8; 16]);
+    let mut server = LocoServerSecureLayer::new();
+
+    let packet = SecurePacket {
+        iv: [1_u8; 16],
+        data: Box::new([0_u8, 1, 2]) as Box<[u8]>,
+    };
+
+    client.handshake(&pub_key).unwrap();
+    client.send(packet.clone());
+
+    swap(&mut server.read_buffer, &mut client.write_buffer);
+
+    assert!(server.read().is_none());
+    assert!(server.send(packet.clone()).is_err());
+
+    assert!(matches!(server.read_handshake(&priv_key), Some(Ok(()))));
+    assert!(server.is_handshaked());
+    assert_eq!(server.read(), Some(packet.clone()));
+
+    server.send(packet.clone()).unwrap();
+    swap(&mut client.read_buffer, &mut server.write_buffer);
+    assert_eq!(client.read(), Some(packet));
+}
+
+#[cfg(feature = "std")]
+#[test]
+pub fn server_incremental_handshake_test() {
+    let priv_key =
+        RsaPrivateKey::new(&mut rand::thread_rng(), 2048).expect("failed to generate a key");
+    let pub_key = RsaPublicKey::from(&priv_key);
+
+    let mut client = LocoClientSecureLayer::new([7_u8; 16]);
+    client.handshake(&pub_key).unwrap();
+
+    let mut server = LocoServerSecureLayer::new();
+
+    let last = client.write_buffer.pop_back().unwrap();
+    for byte in client.write_buffer.drain(..) {
+        server.read_buffer.push_back(byte);
+        assert!(server.read_handshake(&priv_key).is_none());
+    }
+
+    server.read_buffer.push_back(last);
+    assert!(matches!(server.read_handshake(&priv_key), Some(Ok(()))));
+}
+
+#[cfg(feature = "std")]
+#[test]
+pub fn handshake_key_type_test() {
+    let priv_key =
+        RsaPrivateKey::new(&mut rand::thread_rng(), 2048).expect("failed to generate a key");
+    let pub_key = RsaPublicKey::from(&priv_key);
+
+    for key_type in [
+        KeyEncryptType::RsaOaepSha1Mgf1Sha1Old,
+        KeyEncryptType::RsaOaepSha1Mgf1Sha1,
+    ] {
+        let mut client = LocoClientSecureLayer::new([7_u8; 16]);
+        client
+            .handshake_with(&pub_key, key_type, EncryptType::AesCfb128)
+            .unwrap();
+
+        let header = client
+            .write_buffer
+            .range(4..12)
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(&header[..4], &u32::from(key_type).to_le_bytes());
+        assert_eq!(&header[4..], &2_u32.to_le_bytes());
+
+        let mut server = LocoServerSecureLayer::new();
+        assert_eq!(server.key_encrypt_type(), None);
+
+        swap(&mut server.read_buffer, &mut client.write_buffer);
+        assert!(matches!(server.read_handshake(&priv_key), Some(Ok(()))));
+
+        assert_eq!(server.key_encrypt_type(), Some(key_type));
+        assert_eq!(server.encrypt_type(), Some(EncryptType::AesCfb128));
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+pub fn with_random_iv_test() {
+    let a = SecurePacket::with_random_iv([1_u8, 2, 3]);
+    let b = SecurePacket::with_random_iv([1_u8, 2, 3]);
+
+    assert_ne!(a.iv, b.iv);
+    assert_eq!(a.data, b.data);
+}
+
+#[test]
+pub fn send_large_then_small_test() {
+    let mut layer = LocoClientSecureLayer::new([2_u8; 16]);
+
+    let large = SecurePacket {
+        iv: [1_u8; 16],
+        data: vec![0xaa_u8; 1024 * 1024].into_boxed_slice(),
+    };
+    let small = SecurePacket {
+        iv: [2_u8; 16],
+        data: Box::new([1_u8, 2, 3]) as Box<[u8]>,
+    };
+
+    layer.send(large.clone());
+    layer.send(small.clone());
+
+    swap(&mut layer.read_buffer, &mut layer.write_buffer);
+
+    assert_eq!(layer.read(), Some(large));
+    assert_eq!(layer.read(), Some(small));
+    assert_eq!(layer.read(), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+pub fn handshake_oversized_key_test() {
+    use loco_protocol::secure::{server::SecureHandshakeError, MAX_HANDSHAKE_KEY_SIZE};
+
+    let priv_key =
+        RsaPrivateKey::new(&mut rand::thread_rng(), 512).expect("failed to generate a key");
+
+    let mut server = LocoServerSecureLayer::new();
+    server
+        .read_buffer
+        .extend(((MAX_HANDSHAKE_KEY_SIZE + 1) as u32).to_le_bytes());
+
+    assert!(matches!(
+        server.read_handshake(&priv_key),
+        Some(Err(SecureHandshakeError::InvalidKey))
+    ));
+    assert!(!server.is_handshaked());
+}
+
+#[test]
+pub fn reset_test() {
+    let mut layer = LocoClientSecureLayer::new([0_u8; 16]);
+
+    layer.read_buffer.extend(4_u32.to_le_bytes());
+    layer.read_buffer.extend([0_u8; 16 + 4]);
+
+    assert_eq!(layer.read(), None);
+    assert_eq!(layer.read_state(), &ReadState::Corrupted);
+
+    layer.reset();
+    assert_eq!(layer.read_state(), &ReadState::Pending);
+    assert!(layer.read_buffer.is_empty());
+
+    let packet = SecurePacket {
+        iv: [3_u8; 16],
+        data: Box::new([0_u8, 1, 2]) as Box<[u8]>,
+    };
+    layer.send(packet.clone());
+    swap(&mut layer.read_buffer, &mut layer.write_buffer);
+
+    assert_eq!(layer.read(), Some(packet));
+}
+
+#[test]
+pub fn checksum_test() {
+    let packet = SecurePacket {
+        iv: [1_u8; 16],
+        data: Box::new([0_u8, 1, 2]) as Box<[u8]>,
+    };
+
+    // Disabled by default
+    let mut layer = LocoClientSecureLayer::new([5_u8; 16]);
+    layer.send(packet.clone());
+    assert_eq!(layer.write_buffer.len(), 20 + 3);
+
+    let mut layer = LocoClientSecureLayer::new([5_u8; 16]);
+    layer.set_checksum(true);
+    layer.send(packet.clone());
+    layer.send(packet.clone());
+    assert_eq!(layer.write_buffer.len(), (20 + 3 + 4) * 2);
+
+    swap(&mut layer.read_buffer, &mut layer.write_buffer);
+    assert_eq!(layer.read(), Some(packet));
+
+    // Flip a bit in encrypted data of second packet
+    let last = layer.read_buffer.len() - 1;
+    layer.read_buffer[last] ^= 1;
+
+    assert_eq!(layer.read(), None);
+    assert_eq!(layer.read_state(), &ReadState::Corrupted);
+}
+
+#[cfg(feature = "std")]
+#[test]
+pub fn checksum_client_server_test() {
+    let priv_key =
+        RsaPrivateKey::new(&mut rand::thread_rng(), 1024).expect("failed to generate a key");
+    let pub_key = RsaPublicKey::from(&priv_key);
+
+    let mut client = LocoClientSecureLayer::new([7_u8; 16]);
+    client.set_checksum(true);
+
+    let mut server = LocoServerSecureLayer::new();
+    server.set_checksum(true);
+
+    let packet = SecurePacket {
+        iv: [1_u8; 16],
+        data: Box::new([0_u8, 1, 2]) as Box<[u8]>,
+    };
+
+    client.handshake(&pub_key).unwrap();
+    client.send(packet.clone());
+
+    swap(&mut server.read_buffer, &mut client.write_buffer);
+    assert!(matches!(server.read_handshake(&priv_key), Some(Ok(()))));
+    assert_eq!(server.read(), Some(packet.clone()));
+
+    server.send(packet.clone()).unwrap();
+    swap(&mut client.read_buffer, &mut server.write_buffer);
+    assert_eq!(client.read(), Some(packet));
+}
+
+#[test]
+pub fn from_rng_test() {
+    let a = LocoClientSecureLayer::from_rng(&mut StdRng::seed_from_u64(1));
+    let b = LocoClientSecureLayer::from_rng(&mut StdRng::seed_from_u64(1));
+    let c = LocoClientSecureLayer::from_rng(&mut StdRng::seed_from_u64(2));
+
+    assert_eq!(a.key(), b.key());
+    assert_ne!(a.key(), c.key());
+}
+
+#[test]
+pub fn deterministic_rng_test() {
+    let pub_key = RsaPublicKey::from(
+        &RsaPrivateKey::new(&mut rand::thread_rng(), 1024).expect("failed to generate a key"),
+    );
+
+    let write = |seed: u64| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut layer = LocoClientSecureLayer::from_rng(&mut rng);
+
+        layer
+            .handshake_with_rng(
+                &pub_key,
+                KeyEncryptType::default(),
+                EncryptType::default(),
+                &mut rng,
+            )
+            .unwrap();
+        for data in [&[0_u8, 1, 2][..], &[3, 4]] {
+            layer.send(SecurePacket::with_iv_from_rng(data, &mut rng));
+        }
+
+        layer.write_buffer
+    };
+
+    assert_eq!(write(1), write(1));
+    assert_ne!(write(1), write(2));
+}
+
+#[cfg(feature = "std")]
+#[test]
+pub fn server_handshake_failed_test() {
+    use loco_protocol::secure::server::SecureHandshakeError;
+
+    let priv_key =
+        RsaPrivateKey::new(&mut rand::thread_rng(), 1024).expect("failed to generate a key");
+
+    let mut server = LocoServerSecureLayer::new();
+
+    // Unsupported key type followed by packet bytes
+    HandshakePacket::new(
+        0,
+        EncryptType::default().into(),
+        vec![0_u8; 128].into_boxed_slice(),
+    )
+    .encode(&mut server.read_buffer);
+    let mut client = LocoClientSecureLayer::new([7_u8; 16]);
+    client.send(SecurePacket::with_random_iv(&[0_u8, 1, 2][..]));
+    server.read_buffer.extend(client.write_buffer.drain(..));
+
+    assert!(matches!(
+        server.read_handshake(&priv_key),
+        Some(Err(SecureHandshakeError::UnsupportedKeyType(0)))
+    ));
+    assert!(matches!(
+        server.read_handshake(&priv_key),
+        Some(Err(SecureHandshakeError::Failed))
+    ));
+    assert!(!server.is_handshaked());
+    assert_eq!(server.read(), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+pub fn server_reset_test() {
+    use loco_protocol::secure::server::SecureHandshakeError;
+
+    let priv_key =
+        RsaPrivateKey::new(&mut rand::thread_rng(), 1024).expect("failed to generate a key");
+    let pub_key = RsaPublicKey::from(&priv_key);
+
+    let mut server = LocoServerSecureLayer::new();
+    server.set_encrypt_buffer_limit(Some(16));
+
+    server.read_buffer.extend(u32::MAX.to_le_bytes());
+    assert!(matches!(
+        server.read_handshake(&priv_key),
+        Some(Err(SecureHandshakeError::InvalidKey))
+    ));
+
+    server.reset();
+    assert!(server.read_buffer.is_empty());
+
+    let mut client = LocoClientSecureLayer::new([7_u8; 16]);
+    client.handshake(&pub_key).unwrap();
+    swap(&mut server.read_buffer, &mut client.write_buffer);
+    assert!(matches!(server.read_handshake(&priv_key), Some(Ok(()))));
+
+    let large = SecurePacket {
+        iv: [1_u8; 16],
+        data: vec![0xaa_u8; 1024].into_boxed_slice(),
+    };
+    let small = SecurePacket {
+        iv: [2_u8; 16],
+        data: Box::new([1_u8, 2, 3]) as Box<[u8]>,
+    };
+    server.send(large.clone()).unwrap();
+    server.send(small.clone()).unwrap();
+
+    swap(&mut client.read_buffer, &mut server.write_buffer);
+    assert_eq!(client.read(), Some(large));
+    assert_eq!(client.read(), Some(small));
+
+    server.reset();
+    assert!(!server.is_handshaked());
+    assert_eq!(server.read_state(), None);
+}
+
+#[test]
+pub fn server_handshake_with_rng_test() {
+    let mut rng = StdRng::seed_from_u64(3);
+
+    let priv_key = RsaPrivateKey::new(&mut rng, 1024).expect("failed to generate a key");
+    let pub_key = RsaPublicKey::from(&priv_key);
+
+    let mut client = LocoClientSecureLayer::from_rng(&mut rng);
+    client
+        .handshake_with_rng(
+            &pub_key,
+            KeyEncryptType::default(),
+            EncryptType::default(),
+            &mut rng,
+        )
+        .unwrap();
+
+    let mut server = LocoServerSecureLayer::new();
+    swap(&mut server.read_buffer, &mut client.write_buffer);
+    assert!(matches!(
+        server.read_handshake_with_rng(&priv_key, &mut rng),
+        Some(Ok(()))
+    ));
+
+    let packet = SecurePacket::with_iv_from_rng(Box::new([0_u8, 1, 2]) as Box<[u8]>, &mut rng);
+    client.send(packet.clone());
+    swap(&mut server.read_buffer, &mut client.write_buffer);
+    assert_eq!(server.read(), Some(packet));
+}